@@ -0,0 +1,115 @@
+use crate::*;
+
+// instruction of a compiled expression
+#[derive(Clone)]
+pub enum Instr {
+	PushConst(EVar),			// push a value (number, constant or string) on the value stack
+	LoadVar(usize),				// push the value of the variable slot
+	CallOp(RpnEvalFn, u32),		// execute an operator with its number of operands (1 or 2)
+	CallFn(RpnEvalFn, u32),		// execute a function with its number of parameters
+	CallUser(usize, u32),		// execute a function registered in the context, by index, with its number of parameters
+	Jump(usize),				// continue at the given instruction
+	JumpNot(usize),				// pop a condition, continue at the given instruction if false
+	JumpAnd(usize),				// left operand of '&&': if false, replaced by false and continue at the given instruction
+	JumpOr(usize),				// left operand of '||': if true, replaced by true and continue at the given instruction
+}
+
+// compact form of a parsed expression: a flat list of instructions for the stack machine, without
+// the strings and the parsing information of the tokens (hence errors without position)
+// the variables are identified by their slot, as for the Expression it is compiled from
+pub struct CompiledExpression<'a> {
+	program:Vec<Instr>,
+	context:Option<&'a EvalContext>,
+	var_names:Vec<String>,
+	max_depth:usize, // maximum depth of the value stack during the evaluation
+}
+
+impl <'a> CompiledExpression <'a> {
+	pub fn variables(&self) -> &[String] {
+		&self.var_names
+	}
+	pub fn var_slot(&self, name:&str) -> Option<usize> {
+		self.var_names.iter().position(|n| n == name)
+	}
+	pub fn program(&self) -> &[Instr] {
+		&self.program
+	}
+	// evaluation of the program, the value of each variable being given by its slot in 'slots'
+	pub fn eval(&self, slots:&[EVar]) -> Result<EVar, RpnError> {
+		let mut stack:Vec<EVar>=Vec::with_capacity(self.max_depth);
+		let promote=EvalContext::promote(self.context);
+		let mut pc=0;
+		while let Some(instr)=self.program.get(pc) {
+			pc += 1;
+			match instr {
+				Instr::PushConst(val) => stack.push(val.clone()),
+				Instr::LoadVar(slot) => {
+					if let Some(v)=slots.get(*slot) {
+						stack.push(v.clone());
+					}
+					else {
+						return Err(RpnError::Exec(ExErr::VariableNotFound));
+					}
+				},
+				// the numbers of parameters of the builtin functions are checked by the parsing
+				Instr::CallOp(fn_eval, nb_param) | Instr::CallFn(fn_eval, nb_param) =>
+					exec_fn(fn_eval, None, *nb_param as usize, &mut stack, promote)?,
+				Instr::CallUser(idx, nb_param) => {
+					let ufn=match self.context.and_then(|c| c.fns.get(*idx)) {
+						Some(ufn) => ufn,
+						None => return Err(RpnError::Exec(ExErr::EvalNullFn)),
+					};
+					exec_fn(&ufn.fn_eval, ufn.params, *nb_param as usize, &mut stack, promote)?;
+				},
+				Instr::Jump(target) => pc = *target,
+				Instr::JumpNot(target) => if exec_jump(TokKind::JumpNot, &mut stack)? {pc = *target},
+				Instr::JumpAnd(target) => if exec_jump(TokKind::JumpAnd, &mut stack)? {pc = *target},
+				Instr::JumpOr(target) => if exec_jump(TokKind::JumpOr, &mut stack)? {pc = *target},
+			}
+		}
+		if stack.len() != 1 {
+			return Err(RpnError::Exec(ExErr::WrongStackLen));
+		}
+		Ok(stack.pop().unwrap())
+	}
+}
+
+impl <'a> Expression <'a> {
+	// lowers the parse stack into a CompiledExpression
+	pub fn compile(&self) -> Result<CompiledExpression<'a>, ExprError> {
+		if !self.parsed {
+			return Err(RpnError::Exec(ExErr::NotParsed).into());
+		}
+		let mut program:Vec<Instr>=Vec::with_capacity(self.parse_stack.len());
+		let mut depth=0;
+		let mut max_depth=0;
+		for tok in &self.parse_stack {
+			let instr=match tok.kind {
+				TokKind::Number | TokKind::Constant | TokKind::CString => Instr::PushConst(tok.val.clone()),
+				TokKind::Variable => Instr::LoadVar(tok.slot),
+				TokKind::Operator => Instr::CallOp(tok.refdef.fn_eval, tok.nb_param),
+				TokKind::Function => match tok.ufn {
+					Some(idx) => Instr::CallUser(idx, tok.nb_param),
+					None => Instr::CallFn(tok.refdef.fn_eval, tok.nb_param),
+				},
+				// the instructions are one-to-one with the tokens, the jump targets are unchanged
+				TokKind::Jump => Instr::Jump(tok.target),
+				TokKind::JumpNot => Instr::JumpNot(tok.target),
+				TokKind::JumpAnd => Instr::JumpAnd(tok.target),
+				TokKind::JumpOr => Instr::JumpOr(tok.target),
+				_ => return Err(ExprError::at(RpnError::Exec(ExErr::StartWrongToken), tok)),
+			};
+			// operands add one value to the stack, operators and functions replace their parameters by one value
+			// (the branches are counted as if all were evaluated, which can only overestimate the depth)
+			match instr {
+				Instr::PushConst(_) | Instr::LoadVar(_) => depth += 1,
+				Instr::CallOp(_, nb_param) | Instr::CallFn(_, nb_param) | Instr::CallUser(_, nb_param) => depth = depth + 1 - (nb_param as usize).min(depth),
+				Instr::JumpNot(_) => depth -= 1.min(depth),
+				Instr::Jump(_) | Instr::JumpAnd(_) | Instr::JumpOr(_) => {},
+			}
+			max_depth=max_depth.max(depth);
+			program.push(instr);
+		}
+		Ok(CompiledExpression{program, context:self.context, var_names:self.var_names.clone(), max_depth})
+	}
+}
@@ -0,0 +1,186 @@
+use crate::*;
+use std::path::Path;
+
+// error of a set of definitions
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefErr {
+	File(String),			// the definitions file could not be read (message of the I/O error)
+	Syntax,					// line which is not 'name = expression'
+	Duplicate,				// name already defined
+	Reserved,				// name of a constant or of a function, which could not be used as a variable
+	Cycle(Vec<String>),		// names of the definitions depending on each other, the first one repeated last
+	Expr(ExprError),		// analysis, parsing or evaluation error of the expression (columns in the line)
+}
+
+// error of a set of definitions, with the name and the line (from 1) of the definition where it occurs
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefError {
+	pub error:DefErr,
+	pub name:String,
+	pub line:usize,
+}
+
+impl fmt::Display for DefError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.error {
+			DefErr::File(msg) => write!(f, "{}", msg),
+			DefErr::Syntax => write!(f, "line {}: 'name = expression' expected", self.line),
+			DefErr::Duplicate => write!(f, "line {}: '{}' already defined", self.line, self.name),
+			DefErr::Reserved => write!(f, "line {}: '{}' is the name of a constant or a function", self.line, self.name),
+			DefErr::Cycle(names) => write!(f, "line {}: circular definition {}", self.line, names.join(" -> ")),
+			DefErr::Expr(e) => write!(f, "line {}: {}: {}", self.line, self.name, e),
+		}
+	}
+}
+impl std::error::Error for DefError {}
+
+pub(crate) struct Definition<'a> {
+	pub(crate) name:String,
+	pub(crate) line:usize,
+	// position of the expression in its line, in characters
+	pub(crate) column:usize,
+	pub(crate) expr:Expression<'a>,
+	// indexes of the definitions used by the expression
+	pub(crate) deps:Vec<usize>,
+}
+
+// set of derived variables, each defined by an expression of inputs and other derived variables
+// the definitions are given as lines 'name = expression' (empty lines and lines starting with '#' ignored),
+// in any order: they are sorted so that each definition is evaluated after the ones it uses
+pub struct Definitions<'a> {
+	// in evaluation order
+	pub(crate) defs:Vec<Definition<'a>>,
+	pub(crate) inputs:Vec<String>,
+}
+
+impl <'a> Definitions <'a> {
+	pub fn load(path:&Path, context:Option<&'a EvalContext>) -> Result<Definitions<'a>, DefError> {
+		match std::fs::read_to_string(path) {
+			Ok(text) => Definitions::parse(&text, context),
+			Err(e) => Err(DefError{error:DefErr::File(format!("{}: {}", path.display(), e)), name:String::new(), line:0}),
+		}
+	}
+	pub fn parse(text:&str, context:Option<&'a EvalContext>) -> Result<Definitions<'a>, DefError> {
+		let mut defs:Vec<Definition<'a>>=vec![];
+		for (iline, line) in text.lines().enumerate() {
+			let line_nb=iline+1;
+			let trimmed=line.trim();
+			if trimmed.is_empty() || trimmed.starts_with('#') {
+				continue;
+			}
+			let (name, exp)=match line.split_once('=') {
+				Some((name, exp)) if is_name(name.trim()) => (name.trim().to_string(), exp),
+				_ => return Err(DefError{error:DefErr::Syntax, name:String::new(), line:line_nb}),
+			};
+			let column=line.chars().count()-exp.chars().count();
+			if defs.iter().any(|d| d.name == name) {
+				return Err(DefError{error:DefErr::Duplicate, name, line:line_nb});
+			}
+			if is_reserved(&name, context) {
+				return Err(DefError{error:DefErr::Reserved, name, line:line_nb});
+			}
+			let expr=Expression::new(exp, context);
+			if let Some(e)=expr.parse_error() {
+				return Err(DefError{error:DefErr::Expr(in_line(e, column)), name, line:line_nb});
+			}
+			defs.push(Definition{name, line:line_nb, column, expr, deps:vec![]});
+		}
+		// dependencies between the definitions, and inputs (variables which are not defined)
+		let mut inputs:Vec<String>=vec![];
+		for i in 0..defs.len() {
+			let mut deps=vec![];
+			for var in defs[i].expr.variables() {
+				match defs.iter().position(|d| d.name == *var) {
+					Some(idep) => deps.push(idep),
+					None => if !inputs.contains(var) {inputs.push(var.clone())},
+				}
+			}
+			defs[i].deps=deps;
+		}
+		let order=Definitions::sort(&defs)?;
+		// the definitions are moved in evaluation order, with their dependencies renumbered
+		let mut rank=vec![0; defs.len()];
+		for (r, &i) in order.iter().enumerate() {
+			rank[i]=r;
+		}
+		let mut slots:Vec<Option<Definition<'a>>>=defs.into_iter().map(Some).collect();
+		let defs=order.iter().map(|&i| {
+			let mut def=slots[i].take().unwrap();
+			def.deps=def.deps.iter().map(|&d| rank[d]).collect();
+			def
+		}).collect();
+		Ok(Definitions{defs, inputs})
+	}
+	// topological sort by a depth-first search, in the order of the definitions where possible
+	fn sort(defs:&[Definition]) -> Result<Vec<usize>, DefError> {
+		#[derive(Clone, Copy, PartialEq)]
+		enum Mark {
+			New,
+			InProgress,
+			Done,
+		}
+		let mut marks=vec![Mark::New; defs.len()];
+		let mut order=Vec::with_capacity(defs.len());
+		for start in 0..defs.len() {
+			if marks[start] != Mark::New {
+				continue;
+			}
+			// path of the search: definition and index of its next dependency to visit
+			let mut path:Vec<(usize, usize)>=vec![(start, 0)];
+			marks[start]=Mark::InProgress;
+			while let Some(&mut (i, ref mut next))=path.last_mut() {
+				if let Some(&dep)=defs[i].deps.get(*next) {
+					*next += 1;
+					match marks[dep] {
+						Mark::New => {
+							marks[dep]=Mark::InProgress;
+							path.push((dep, 0));
+						},
+						Mark::InProgress => {
+							// dep is on the path: the cycle goes from it to the current definition
+							let istart=path.iter().position(|p| p.0 == dep).unwrap();
+							let mut names:Vec<String>=path[istart..].iter().map(|p| defs[p.0].name.clone()).collect();
+							names.push(defs[dep].name.clone());
+							return Err(DefError{error:DefErr::Cycle(names), name:defs[dep].name.clone(), line:defs[dep].line});
+						},
+						Mark::Done => {},
+					}
+				}
+				else {
+					marks[i]=Mark::Done;
+					order.push(i);
+					path.pop();
+				}
+			}
+		}
+		Ok(order)
+	}
+	// names of the definitions, in evaluation order
+	pub fn names(&self) -> Vec<&str> {
+		self.defs.iter().map(|d| d.name.as_str()).collect()
+	}
+	// names of the variables used by the definitions which are not defined, to be given at evaluation
+	pub fn inputs(&self) -> &[String] {
+		&self.inputs
+	}
+	// evaluation of all the definitions with the values of the inputs; returns the values of the definitions
+	pub fn eval(&mut self, inputs:&HashMap<String, EVar>) -> Result<HashMap<String, EVar>, DefError> {
+		let mut vars=inputs.clone();
+		let mut values=HashMap::with_capacity(self.defs.len());
+		for def in self.defs.iter_mut() {
+			let val=match def.expr.eval_with(&vars) {
+				Ok(val) => val.unwrap_or(EVar::Null),
+				Err(e) => return Err(DefError{error:DefErr::Expr(in_line(e, def.column)), name:def.name.clone(), line:def.line}),
+			};
+			vars.insert(def.name.clone(), val.clone());
+			values.insert(def.name.clone(), val);
+		}
+		Ok(values)
+	}
+}
+
+// error of an expression located in the line of its definition
+pub(crate) fn in_line(mut e:ExprError, column:usize) -> ExprError {
+	e.span=e.span.map(|span| Span{start:span.start+column, end:span.end+column});
+	e
+}
@@ -1,243 +1,879 @@
-
 use crate::*;
 use std::time::*;
 
-// look for first operand available for evaluaton in the vector of Tokens 'tokens', from position 'istart', going backwards
-// returns a tuple: index of the token found in tokens and a reference to this token
-// NB side effect: the token found will be consumed (val_consumed set to true)
-pub fn get_operand (istart:usize, tokens:& Vec<Token>) ->  Result<(usize, &Token), RpnError> {
-	if istart==0 {
-		return Err(RpnError::Exec(ExErr::GetOperandStart));
-	}
-	for i in (0..istart).rev() {
-		if let Some(op)=tokens.get(i) {
-			if op.val_consumed.get()==false /*&& 
-				(op.kind==TokKind::Number || op.kind==TokKind::Constant || op.kind==TokKind::CString)*/ {
-				op.val_consumed.set(true);
-				return Ok((i,op));
+// execution of an operator or a function on the value stack 'stack' of the evaluator:
+// its 'nb_param' parameters are taken from the top of the stack (first parameter deepest) and 
+// replaced by the result of the evaluation function
+// 'params' is the number of parameters expected by a function (None if variable, or for an operator)
+// with 'promote', an integer overflow is not an error: the evaluation is done again with the
+// integer parameters converted to floats
+pub fn exec_fn (fn_eval:&dyn Fn(&[EVar]) -> Result<EVar, RpnError>, params:Option<u32>, nb_param:usize, stack:&mut Vec<EVar>, promote:bool) -> Result<(), RpnError> {
+	match params {
+		Some(n) if (n as usize) < nb_param => return Err(RpnError::Exec(ExErr::TooManyParams)),
+		Some(n) if (n as usize) > nb_param => return Err(RpnError::Exec(ExErr::GetOperandMissing)),
+		_ => {},
+	}
+	if nb_param > stack.len() {
+		return Err(RpnError::Exec(ExErr::GetOperandMissing));
+	}
+	let ipar=stack.len()-nb_param;
+	let rv=match fn_eval(&stack[ipar..]) {
+		Err(RpnError::Exec(ExErr::Overflow)) if promote => {
+			let params:Vec<EVar>=stack[ipar..].iter().map(|p| match p {
+				EVar::IVal(i) => EVar::FVal(*i as f64),
+				_ => p.clone(),
+			}).collect();
+			fn_eval(&params)?
+		},
+		rv => rv?,
+	};
+	stack.truncate(ipar);
+	stack.push(rv);
+	return Ok(());
+}
+
+// execution of a jump of the parse stack on the value stack 'stack' of the evaluator
+// returns true if the jump must be taken
+pub fn exec_jump (kind:TokKind, stack:&mut Vec<EVar>) -> Result<bool, RpnError> {
+	match kind {
+		TokKind::Jump => Ok(true),
+		TokKind::JumpNot => {
+			match stack.pop() {
+				Some(cond) => Ok(!cond.to_bool()),
+				None => Err(RpnError::Exec(ExErr::GetOperandMissing)),
 			}
-		}
+		},
+		TokKind::JumpAnd | TokKind::JumpOr => {
+			// the left operand decides alone of the result if it is false for '&&', true for '||'
+			let decisive=kind == TokKind::JumpOr;
+			match stack.last_mut() {
+				Some(op) => {
+					if op.to_bool() == decisive {
+						*op=EVar::BVal(decisive);
+						return Ok(true);
+					}
+					Ok(false)
+				},
+				None => Err(RpnError::Exec(ExErr::GetOperandMissing)),
+			}
+		},
+		_ => Ok(false),
 	}
- 	Err(RpnError::Exec(ExErr::GetOperandMissing))
 }
 
-pub fn eval_null(_:usize, _:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
+// true if one of the parameters is null: the result of most functions is then null
+fn has_null(params:&[EVar]) -> bool {
+	return params.iter().any(|p| p.is_null());
+}
+
+pub fn eval_null(_:&[EVar]) -> Result<EVar, RpnError> {
 	Err(RpnError::Exec(ExErr::EvalNullFn))
 }
 
-pub fn eval_plus(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	if nb_param == 1 { // nb_param=1 => unary operator
-		return Ok(op1.val.clone());
+// result of a checked integer operation, None meaning an overflow
+fn checked(rv:Option<EVar>) -> Result<EVar, RpnError> {
+	return rv.ok_or(RpnError::Exec(ExErr::Overflow));
+}
+
+pub fn eval_plus(params:&[EVar]) -> Result<EVar, RpnError> {
+	if params.len() == 1 { // one parameter => unary operator
+		return Ok(params[0].clone());
 	}
 	else {
-		let (_, op2) = get_operand (i_1, tokens)?;
-		return Ok(op2.val.clone()+op1.val.clone());
+		return checked(params[0].checked_add(&params[1]));
 	}
 }
 
-pub fn eval_sub(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	if nb_param == 1 { // nb_param=1 => unary operator
-		return Ok(EVar::IVal(0)-op1.val.clone());
+pub fn eval_sub(params:&[EVar]) -> Result<EVar, RpnError> {
+	if params.len() == 1 { // one parameter => unary operator
+		return checked(EVar::IVal(0).checked_sub(&params[0]));
 	}
 	else {
-		let (_, op2) = get_operand (i_1, tokens)?;
-		return Ok(op2.val.clone()-op1.val.clone());
+		return checked(params[0].checked_sub(&params[1]));
 	}
 }
 
-pub fn eval_mul(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(op2.val.clone()*op1.val.clone());
+pub fn eval_mul(params:&[EVar]) -> Result<EVar, RpnError> {
+	return checked(params[0].checked_mul(&params[1]));
 }
 
-pub fn eval_div(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	match op1.val {
+// check of the divisor of the divisions and of the remainder
+fn check_divisor(divisor:&EVar) -> Result<(), RpnError> {
+	match *divisor {
 			EVar::IVal(i) => if i == 0 {return Err(RpnError::Exec(ExErr::EvalDiv0));}
 			EVar::FVal(f) => if f == 0.0 {return Err(RpnError::Exec(ExErr::EvalDiv0));}
 			EVar::BVal(b) => if b == false {return Err(RpnError::Exec(ExErr::EvalDiv0));}
 			EVar::SVal(_) => return Err(RpnError::Exec(ExErr::EvalDiv0)),
+			EVar::Null => {},
+	}
+	return Ok(());
+}
+
+pub fn eval_div(params:&[EVar]) -> Result<EVar, RpnError> {
+	// a null dividend or divisor gives null before any check of the divisor
+	if has_null(params) {
+		return Ok(EVar::Null);
 	}
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(op2.val.clone()/op1.val.clone())
+	check_divisor(&params[1])?;
+	return checked(params[0].checked_div(&params[1]));
+}
+
+// '%': remainder of the division, of the sign of the dividend
+pub fn eval_rem(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	check_divisor(&params[1])?;
+	return checked(params[0].checked_rem(&params[1]));
+}
+
+// '//': division rounded toward minus infinity
+pub fn eval_floordiv(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	check_divisor(&params[1])?;
+	return checked(params[0].checked_floordiv(&params[1]));
 }
 
 // comparison functions 
-pub fn eval_eq(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(op2.val.ev_eq(&op1.val)));
+pub fn eval_eq(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].ev_eq(&params[1])));
 }
-pub fn eval_neq(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(!op2.val.ev_eq(&op1.val)));
+pub fn eval_neq(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(!params[0].ev_eq(&params[1])));
 }
-pub fn eval_infeq(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(op2.val.ev_infeq(&op1.val)));
+pub fn eval_infeq(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].ev_infeq(&params[1])));
 }
-pub fn eval_inf(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(op2.val.ev_inf(&op1.val)));
+pub fn eval_inf(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].ev_inf(&params[1])));
 }
-pub fn eval_supeq(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(op2.val.ev_supeq(&op1.val)));
+pub fn eval_supeq(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].ev_supeq(&params[1])));
 }
-pub fn eval_sup(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal(op2.val.ev_sup(&op1.val)));
+pub fn eval_sup(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].ev_sup(&params[1])));
 }
 
 // bit-wise operations
-pub fn eval_bitnot(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(EVar::IVal(op1.val.ev_bitnot()));
+pub fn eval_bitnot(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].ev_bitnot()));
+}
+pub fn eval_band(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].ev_band(&params[1])));
+}
+pub fn eval_bor(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].ev_bor(&params[1])));
+}
+pub fn eval_bitxor(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].ev_bitxor(&params[1])));
+}
+pub fn eval_shl(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return params[0].checked_shl(&params[1]).map(EVar::IVal).ok_or(RpnError::Exec(ExErr::Overflow));
+}
+pub fn eval_shr(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return params[0].checked_shr(&params[1]).map(EVar::IVal).ok_or(RpnError::Exec(ExErr::Overflow));
 }
-pub fn eval_band(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::IVal(op2.val.ev_band(&op1.val)));
+
+// bit functions; the bits are numbered from 0 (least significant bit) to 63
+fn bit_index(n:&EVar, max:i64) -> Result<u32, RpnError> {
+	match n.to_int() {
+		i @ 0..=63 if i <= max => Ok(i as u32),
+		_ => Err(RpnError::Exec(ExErr::EvalBadParam)),
+	}
+}
+
+// bit(x, n): true if the bit n of x is set
+pub fn eval_bit(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::BVal(params[0].to_int() & (1<<bit_index(&params[1], 63)?) != 0));
+}
+
+pub fn eval_setbit(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int() | (1<<bit_index(&params[1], 63)?)));
 }
-pub fn eval_bor(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::IVal(op2.val.ev_bor(&op1.val)));
+
+pub fn eval_clrbit(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int() & !(1<<bit_index(&params[1], 63)?)));
 }
-pub fn eval_bitxor(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::IVal(op2.val.ev_bitxor(&op1.val)));
+
+// bits(x, lo, hi): field of the bits lo to hi (included) of x, shifted to the bit 0
+pub fn eval_bits(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let lo=bit_index(&params[1], 63)?;
+	let hi=bit_index(&params[2], 63)?;
+	if lo > hi {
+		return Err(RpnError::Exec(ExErr::EvalBadParam));
+	}
+	let field=(params[0].to_int() as u64)>>lo;
+	let width=hi-lo+1;
+	return Ok(EVar::IVal(if width == 64 {field} else {field & ((1<<width)-1)} as i64));
 }
-pub fn eval_shl(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::IVal(op2.val.ev_shl(&op1.val)));
+
+pub fn eval_popcount(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int().count_ones() as i64));
 }
-pub fn eval_shr(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::IVal(op2.val.ev_shr(&op1.val)));
+
+// rotation of the 'width' least significant bits of x (the other bits are cleared)
+fn rotate(params:&[EVar], left:bool) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let width=match params[2].to_int() {
+		w @ 1..=64 => w as u32,
+		_ => return Err(RpnError::Exec(ExErr::EvalBadParam)),
+	};
+	let mask=if width == 64 {u64::MAX} else {(1<<width)-1};
+	let x=(params[0].to_int() as u64) & mask;
+	let n=params[1].to_int().rem_euclid(width as i64) as u32;
+	let n=if left {n} else {(width-n)%width};
+	if n == 0 {
+		return Ok(EVar::IVal(x as i64));
+	}
+	return Ok(EVar::IVal((((x<<n) | (x>>(width-n))) & mask) as i64));
+}
+
+// rotl(x, n, width)
+pub fn eval_rotl(params:&[EVar]) -> Result<EVar, RpnError> {
+	return rotate(params, true);
+}
+
+// rotr(x, n, width)
+pub fn eval_rotr(params:&[EVar]) -> Result<EVar, RpnError> {
+	return rotate(params, false);
+}
+
+// byte swaps of the 2, 4 or 8 least significant bytes
+pub fn eval_bswap16(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal((params[0].to_int() as u16).swap_bytes() as i64));
+}
+
+pub fn eval_bswap32(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal((params[0].to_int() as u32).swap_bytes() as i64));
+}
+
+pub fn eval_bswap64(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int().swap_bytes()));
+}
+
+// decoding of the values read from 16-bit registers (e.g. Modbus); the registers are given in the
+// order they are read, and only their 16 least significant bits are used
+// the suffix of the function gives the order of the bytes A (most significant) to D in the registers,
+// the default being ABCD (big-endian, most significant word first)
+#[derive(Clone, Copy)]
+enum RegOrder {
+	Abcd,
+	Cdab,
+	Badc,
+	Dcba,
+}
+
+fn regs_to_u32(params:&[EVar], order:RegOrder) -> u32 {
+	let (r0, r1)=(params[0].to_int() as u16, params[1].to_int() as u16);
+	let (hi, lo)=match order {
+		RegOrder::Abcd => (r0, r1),
+		RegOrder::Cdab => (r1, r0),
+		RegOrder::Badc => (r0.swap_bytes(), r1.swap_bytes()),
+		RegOrder::Dcba => (r1.swap_bytes(), r0.swap_bytes()),
+	};
+	return ((hi as u32)<<16) | lo as u32;
+}
+
+fn f32_from_regs(params:&[EVar], order:RegOrder) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::FVal(f32::from_bits(regs_to_u32(params, order)) as f64));
+}
+
+fn i32_from_regs(params:&[EVar], order:RegOrder) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(regs_to_u32(params, order) as i32 as i64));
+}
+
+fn u32_from_regs(params:&[EVar], order:RegOrder) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(regs_to_u32(params, order) as i64));
+}
+
+pub fn eval_f32_regs(params:&[EVar]) -> Result<EVar, RpnError> {
+	return f32_from_regs(params, RegOrder::Abcd);
+}
+pub fn eval_f32_regs_cdab(params:&[EVar]) -> Result<EVar, RpnError> {
+	return f32_from_regs(params, RegOrder::Cdab);
+}
+pub fn eval_f32_regs_badc(params:&[EVar]) -> Result<EVar, RpnError> {
+	return f32_from_regs(params, RegOrder::Badc);
+}
+pub fn eval_f32_regs_dcba(params:&[EVar]) -> Result<EVar, RpnError> {
+	return f32_from_regs(params, RegOrder::Dcba);
+}
+pub fn eval_i32_regs(params:&[EVar]) -> Result<EVar, RpnError> {
+	return i32_from_regs(params, RegOrder::Abcd);
+}
+pub fn eval_i32_regs_cdab(params:&[EVar]) -> Result<EVar, RpnError> {
+	return i32_from_regs(params, RegOrder::Cdab);
+}
+pub fn eval_i32_regs_badc(params:&[EVar]) -> Result<EVar, RpnError> {
+	return i32_from_regs(params, RegOrder::Badc);
+}
+pub fn eval_i32_regs_dcba(params:&[EVar]) -> Result<EVar, RpnError> {
+	return i32_from_regs(params, RegOrder::Dcba);
+}
+pub fn eval_u32_regs(params:&[EVar]) -> Result<EVar, RpnError> {
+	return u32_from_regs(params, RegOrder::Abcd);
+}
+pub fn eval_u32_regs_cdab(params:&[EVar]) -> Result<EVar, RpnError> {
+	return u32_from_regs(params, RegOrder::Cdab);
+}
+pub fn eval_u32_regs_badc(params:&[EVar]) -> Result<EVar, RpnError> {
+	return u32_from_regs(params, RegOrder::Badc);
+}
+pub fn eval_u32_regs_dcba(params:&[EVar]) -> Result<EVar, RpnError> {
+	return u32_from_regs(params, RegOrder::Dcba);
+}
+
+// register as an unsigned value
+pub fn eval_u16(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int() as u16 as i64));
+}
+
+// register as a signed value (two's complement)
+pub fn eval_i16(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_int() as u16 as i16 as i64));
+}
+
+// value of a binary-coded decimal, e.g. 0x1234 is 1234; an error if a digit is not 0 to 9
+pub fn eval_bcd(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let mut x=params[0].to_int();
+	if x < 0 {
+		return Err(RpnError::Exec(ExErr::EvalBadParam));
+	}
+	let mut rv=0;
+	let mut scale=1;
+	while x != 0 {
+		let digit=x & 0xf;
+		if digit > 9 {
+			return Err(RpnError::Exec(ExErr::EvalBadParam));
+		}
+		rv += digit*scale;
+		x >>= 4;
+		// a 64-bit value has 16 digits at most, 10^15 being the last scale used
+		scale=scale.saturating_mul(10);
+	}
+	return Ok(EVar::IVal(rv));
+}
+
+// integer functions
+fn gcd(a:i64, b:i64) -> Result<i64, RpnError> {
+	let (mut a, mut b)=(a.unsigned_abs(), b.unsigned_abs());
+	while b != 0 {
+		(a, b)=(b, a%b);
+	}
+	return i64::try_from(a).map_err(|_| RpnError::Exec(ExErr::Overflow));
+}
+
+pub fn eval_gcd(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(gcd(params[0].to_int(), params[1].to_int())?));
+}
+
+pub fn eval_lcm(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let (a, b)=(params[0].to_int(), params[1].to_int());
+	if a == 0 || b == 0 {
+		return Ok(EVar::IVal(0));
+	}
+	return checked((a/gcd(a, b)?).checked_mul(b).and_then(i64::checked_abs).map(EVar::IVal));
+}
+
+pub fn eval_factorial(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let n=params[0].to_int();
+	if n < 0 {
+		return Err(RpnError::Exec(ExErr::EvalBadParam));
+	}
+	return checked((2..=n).try_fold(1i64, |acc, i| acc.checked_mul(i)).map(EVar::IVal));
 }
 
 // logical operations
-pub fn eval_and(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal((op2.val == (EVar::BVal(true))) && (op1.val == (EVar::BVal(true)))));
+pub fn eval_and(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal((params[0] == (EVar::BVal(true))) && (params[1] == (EVar::BVal(true)))));
+}
+
+pub fn eval_or(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal((params[0] == (EVar::BVal(true))) || (params[1] == (EVar::BVal(true)))));
+}
+
+// conditional, for the function 'if' and the ternary operator
+// NB: the evaluation is normally lazy, only one of the branches being evaluated (see link_branches)
+pub fn eval_if(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(if params[0].to_bool() {params[1].clone()} else {params[2].clone()});
+}
+
+pub fn eval_lognot(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::BVal(params[0].ev_lognot()));
+}
+
+pub fn eval_sin(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].sin());
 }
 
-pub fn eval_or(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(EVar::BVal((op2.val == (EVar::BVal(true))) || (op1.val == (EVar::BVal(true)))));
+pub fn eval_cos(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].cos());
 }
 
-pub fn eval_lognot(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(EVar::BVal(op1.val.ev_lognot()));
+pub fn eval_tan(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].tan());
 }
 
-pub fn eval_sin(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.sin());
+pub fn eval_asin(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].asin());
 }
 
-pub fn eval_cos(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.cos());
+pub fn eval_acos(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].acos());
 }
 
-pub fn eval_tan(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.tan());
+pub fn eval_atan(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].atan());
 }
 
-pub fn eval_exp(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.exp());
+// atan2(y, x)
+pub fn eval_atan2(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].atan2(&params[1]));
 }
 
-pub fn eval_ln(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.ln());
+pub fn eval_sinh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].sinh());
 }
 
-pub fn eval_log10(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.log10());
+pub fn eval_cosh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].cosh());
 }
 
-pub fn eval_pow(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (i_1, op1) = get_operand (i, tokens)?;
-	let (_, op2) = get_operand (i_1, tokens)?;
-	return Ok(op2.val.pow(&op1.val));
+pub fn eval_tanh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].tanh());
 }
 
-pub fn eval_sqrt(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.sqrt());
+pub fn eval_asinh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].asinh());
 }
 
-pub fn eval_cbrt(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	return Ok(op1.val.cbrt());
+pub fn eval_acosh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].acosh());
 }
 
-pub fn eval_max(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError> {
-	if nb_param>0 {
-		let (j, opj) = get_operand (i, tokens)?;
-		let mut idx=j;
-		let mut rv=opj.val.clone();
-		for _ in 0..nb_param -1 {
-			let (k, opk) = get_operand (idx, tokens)?;
-			rv=rv.max(&opk.val);
-			idx=k;
+pub fn eval_atanh(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].atanh());
+}
+
+pub fn eval_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].deg());
+}
+
+pub fn eval_rad(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].rad());
+}
+
+// trigonometric functions with angles in degrees
+pub fn eval_sin_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].rad().sin());
+}
+
+pub fn eval_cos_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].rad().cos());
+}
+
+pub fn eval_tan_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].rad().tan());
+}
+
+pub fn eval_asin_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].asin().deg());
+}
+
+pub fn eval_acos_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].acos().deg());
+}
+
+pub fn eval_atan_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].atan().deg());
+}
+
+pub fn eval_atan2_deg(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].atan2(&params[1]).deg());
+}
+
+pub fn eval_exp(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].exp());
+}
+
+pub fn eval_ln(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].ln());
+}
+
+pub fn eval_log10(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].log10());
+}
+
+pub fn eval_pow(params:&[EVar]) -> Result<EVar, RpnError> {
+	return checked(params[0].checked_pow(&params[1]));
+}
+
+pub fn eval_sqrt(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].sqrt());
+}
+
+pub fn eval_cbrt(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].cbrt());
+}
+
+pub fn eval_abs(params:&[EVar]) -> Result<EVar, RpnError> {
+	return checked(params[0].checked_abs());
+}
+
+pub fn eval_sign(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].sign());
+}
+
+// round(x, digits)
+pub fn eval_round(params:&[EVar]) -> Result<EVar, RpnError> {
+	return checked(params[0].checked_round(&params[1]));
+}
+
+pub fn eval_floor(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].floor());
+}
+
+pub fn eval_ceil(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].ceil());
+}
+
+pub fn eval_trunc(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].trunc());
+}
+
+pub fn eval_frac(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].frac());
+}
+
+// clamp(x, lo, hi)
+pub fn eval_clamp(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(params[0].max(&params[1]).min(&params[2]));
+}
+
+pub fn eval_hypot(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].hypot(&params[1]));
+}
+
+// log(x, base)
+pub fn eval_log(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].log(&params[1]));
+}
+
+pub fn eval_log2(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params[0].log2());
+}
+
+pub fn eval_max(params:&[EVar]) -> Result<EVar, RpnError> {
+	if let Some((first, others))=params.split_first() {
+		let mut rv=first.clone();
+		for op in others {
+			rv=rv.max(op);
 		}
 		return Ok(rv);
 	}
 	Err(RpnError::Exec(ExErr::EvalMaxParam))
 }
 
-pub fn eval_min(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError> {
-	if nb_param>0 {
-		let (j, opj) = get_operand (i, tokens)?;
-		let mut idx=j;
-		let mut rv=opj.val.clone();
-		for _ in 0..nb_param -1 {
-			let (k, opk) = get_operand (idx, tokens)?;
-			rv=rv.min(&opk.val);
-			idx=k;
+pub fn eval_min(params:&[EVar]) -> Result<EVar, RpnError> {
+	if let Some((first, others))=params.split_first() {
+		let mut rv=first.clone();
+		for op in others {
+			rv=rv.min(op);
 		}
 		return Ok(rv);
 	}
 	Err(RpnError::Exec(ExErr::EvalMaxParam))
 }
 
-pub fn eval_avg(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError> {
-	if nb_param>0 {
-		let (j, opj) = get_operand (i, tokens)?;
-		let mut idx=j;
-		let mut rv=opj.val.clone();
-		for _ in 0..nb_param -1 {
-			let (k, opk) = get_operand (idx, tokens)?;
-			rv = rv + opk.val.clone();
-			idx=k;
+// the null parameters are skipped, the average of null parameters only is null
+pub fn eval_avg(params:&[EVar]) -> Result<EVar, RpnError> {
+	if params.is_empty() {
+		return Err(RpnError::Exec(ExErr::EvalAvgParam));
+	}
+	let mut rv=EVar::Null;
+	let mut nb=0;
+	for op in params.iter().filter(|p| !p.is_null()) {
+		rv = if nb == 0 {op.clone()} else {rv + op.clone()};
+		nb += 1;
+	}
+	if nb == 0 {
+		return Ok(rv);
+	}
+	return Ok(rv/EVar::FVal(nb as f64));
+}
+
+// statistics: like avg, the null parameters are skipped and the result of null parameters only is null
+// integers give integers for sum, prod, range, mode and for the median of an odd number of values
+fn stat_values(params:&[EVar]) -> Result<Vec<&EVar>, RpnError> {
+	if params.is_empty() {
+		return Err(RpnError::Exec(ExErr::EvalStatParam));
+	}
+	return Ok(params.iter().filter(|p| !p.is_null()).collect());
+}
+
+// values sorted in ascending order
+fn stat_sorted(values:&[&EVar]) -> Vec<EVar> {
+	let mut sorted:Vec<EVar>=values.iter().map(|&v| v.clone()).collect();
+	sorted.sort_by(|v1, v2| v1.to_float().total_cmp(&v2.to_float()));
+	return sorted;
+}
+
+pub fn eval_sum(params:&[EVar]) -> Result<EVar, RpnError> {
+	let mut rv=EVar::Null;
+	for (i, op) in stat_values(params)?.into_iter().enumerate() {
+		rv = if i == 0 {op.clone()} else {checked(rv.checked_add(op))?};
+	}
+	return Ok(rv);
+}
+
+pub fn eval_prod(params:&[EVar]) -> Result<EVar, RpnError> {
+	let mut rv=EVar::Null;
+	for (i, op) in stat_values(params)?.into_iter().enumerate() {
+		rv = if i == 0 {op.clone()} else {checked(rv.checked_mul(op))?};
+	}
+	return Ok(rv);
+}
+
+// number of values which are not null
+pub fn eval_count(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::IVal(stat_values(params)?.len() as i64));
+}
+
+pub fn eval_median(params:&[EVar]) -> Result<EVar, RpnError> {
+	let sorted=stat_sorted(&stat_values(params)?);
+	let nb=sorted.len();
+	if nb == 0 {
+		return Ok(EVar::Null);
+	}
+	if nb%2 == 1 {
+		return Ok(sorted[nb/2].clone());
+	}
+	return Ok(EVar::FVal((sorted[nb/2-1].to_float()+sorted[nb/2].to_float())/2.0));
+}
+
+// population variance
+pub fn eval_variance(params:&[EVar]) -> Result<EVar, RpnError> {
+	let values=stat_values(params)?;
+	if values.is_empty() {
+		return Ok(EVar::Null);
+	}
+	let nb=values.len() as f64;
+	let mean=values.iter().map(|v| v.to_float()).sum::<f64>()/nb;
+	return Ok(EVar::FVal(values.iter().map(|v| (v.to_float()-mean).powi(2)).sum::<f64>()/nb));
+}
+
+// population standard deviation
+pub fn eval_stddev(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(eval_variance(params)?.sqrt());
+}
+
+// max - min
+pub fn eval_range(params:&[EVar]) -> Result<EVar, RpnError> {
+	stat_values(params)?;
+	return checked(eval_max(params)?.checked_sub(&eval_min(params)?));
+}
+
+// most frequent value; with several, the first one in the parameters
+pub fn eval_mode(params:&[EVar]) -> Result<EVar, RpnError> {
+	let values=stat_values(params)?;
+	let mut rv=EVar::Null;
+	let mut nb_max=0;
+	for v in &values {
+		let nb=values.iter().filter(|&w| v.ev_eq(w)).count();
+		if nb > nb_max {
+			nb_max=nb;
+			rv=(*v).clone();
 		}
-		return Ok(rv/EVar::FVal(nb_param as f64));
 	}
-	Err(RpnError::Exec(ExErr::EvalAvgParam))
+	return Ok(rv);
+}
+
+// percentile(p, ...): value below which p percent of the values are, with a linear interpolation
+// between the values
+pub fn eval_percentile(params:&[EVar]) -> Result<EVar, RpnError> {
+	let (p, others)=match params.split_first() {
+		Some((p, others)) if !others.is_empty() => (p, others),
+		_ => return Err(RpnError::Exec(ExErr::EvalStatParam)),
+	};
+	if p.is_null() {
+		return Ok(EVar::Null);
+	}
+	let p=p.to_float();
+	if !(0.0..=100.0).contains(&p) {
+		return Err(RpnError::Exec(ExErr::EvalBadParam));
+	}
+	let sorted=stat_sorted(&stat_values(others)?);
+	if sorted.is_empty() {
+		return Ok(EVar::Null);
+	}
+	let pos=p/100.0*(sorted.len()-1) as f64;
+	let (lo, hi)=(sorted[pos.floor() as usize].to_float(), sorted[pos.ceil() as usize].to_float());
+	return Ok(EVar::FVal(lo+(hi-lo)*pos.fract()));
+}
+
+pub fn eval_isnull(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(EVar::BVal(params[0].is_null()));
 }
 
-pub fn eval_now(_:usize, _:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
+// first parameter which is not null, null if all are
+pub fn eval_coalesce(params:&[EVar]) -> Result<EVar, RpnError> {
+	return Ok(params.iter().find(|p| !p.is_null()).cloned().unwrap_or(EVar::Null));
+}
+
+pub fn eval_now(_:&[EVar]) -> Result<EVar, RpnError> {
 	Ok(EVar::IVal(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
 		.unwrap_or(Duration::new(0, 0)).as_secs() as i64))
 }
+
+// string functions; the parameters which are not strings are used with their string representation
+// NB: positions and lengths are in characters, the result is null if a parameter is null
+pub fn eval_len(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::IVal(params[0].to_string().chars().count() as i64));
+}
+
+pub fn eval_upper(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::SVal(params[0].to_string().to_uppercase()));
+}
+
+pub fn eval_lower(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::SVal(params[0].to_string().to_lowercase()));
+}
+
+pub fn eval_trim(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::SVal(params[0].to_string().trim().to_string()));
+}
+
+// substr(s, start, len)
+pub fn eval_substr(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let start=params[1].to_int().max(0) as usize;
+	let len=params[2].to_int().max(0) as usize;
+	return Ok(EVar::SVal(params[0].to_string().chars().skip(start).take(len).collect()));
+}
+
+// find(s, sub): position of the first occurrence of sub in s, -1 if not found
+pub fn eval_find(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	let s=params[0].to_string();
+	match s.find(&params[1].to_string()) {
+		Some(i) => Ok(EVar::IVal(s[..i].chars().count() as i64)),
+		None => Ok(EVar::IVal(-1)),
+	}
+}
+
+// replace(s, from, to): all the occurrences are replaced
+pub fn eval_replace(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::SVal(params[0].to_string().replace(&params[1].to_string(), &params[2].to_string())));
+}
+
+pub fn eval_startswith(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::BVal(params[0].to_string().starts_with(&params[1].to_string())));
+}
+
+pub fn eval_endswith(params:&[EVar]) -> Result<EVar, RpnError> {
+	if has_null(params) {
+		return Ok(EVar::Null);
+	}
+	return Ok(EVar::BVal(params[0].to_string().ends_with(&params[1].to_string())));
+}
@@ -1,7 +1,6 @@
 use core::str::Chars;
 use std::fmt;
-use std::cell::Cell;
-use std::rc::Rc;
+use std::collections::HashMap;
 
 pub mod srlvariant;
 use crate::srlvariant::*;
@@ -9,7 +8,12 @@ use crate::srlvariant::*;
 pub mod eval;
 use crate::eval::*;
 
-#[derive(Clone, Copy)]
+pub mod compile;
+pub mod stack;
+pub mod derived;
+pub mod workspace;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum LexErr {
 	BadStart,
 	FunctionOrConstNotFound,
@@ -24,14 +28,18 @@ pub enum LexErr {
 	QuoteAfterOp,
 	BadOperatorShort,
 	BadOperatorLong,
+	UnterminatedString,
+	BadEscape,
 }
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ParErr {
 	ParamNb,
 	MatchingPar,
 	ParamSep,
+	MatchingTernary,
+	MissingOperand,
 }
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExErr {
 	NotParsed,
 	EvalNullFn,
@@ -45,9 +53,62 @@ pub enum ExErr {
 	EvalDiv0,
 	EvalMaxParam,
 	EvalAvgParam,
+	VariableNotFound,
+	Overflow,
+	EvalStatParam,
+	EvalBadParam,
+}
+
+impl fmt::Display for LexErr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let err_msg = match *self {
+			LexErr::BadStart => "unexpected character",
+			LexErr::FunctionOrConstNotFound => "unknown function",
+			LexErr::FunctionOrConst => "invalid name",
+			LexErr::BadHexInit | LexErr::BadSigBdp | LexErr::BadSigAdp => "invalid number",
+			LexErr::BadExpSign | LexErr::BadExpASign | LexErr::BadExpVal => "invalid exponent",
+			LexErr::BadHex => "invalid hexadecimal number",
+			LexErr::QuoteAfterOp => "quote right after an operator",
+			LexErr::BadOperatorShort | LexErr::BadOperatorLong => "unknown operator",
+			LexErr::UnterminatedString => "unterminated string",
+			LexErr::BadEscape => "invalid escape sequence in string",
+		};
+		write!(f, "{}", err_msg)
+	}
+}
+impl fmt::Display for ParErr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let err_msg = match *self {
+			ParErr::ParamNb => "wrong number of parameters for",
+			ParErr::MatchingPar => "unbalanced parenthesis",
+			ParErr::ParamSep => "misplaced separator",
+			ParErr::MatchingTernary => "unbalanced '?' and ':' of ternary operator",
+			ParErr::MissingOperand => "missing left operand for",
+		};
+		write!(f, "{}", err_msg)
+	}
+}
+impl fmt::Display for ExErr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let err_msg = match *self {
+			ExErr::NotParsed => "expression not parsed",
+			ExErr::EvalNullFn => "no evaluation function for",
+			ExErr::TooManyParams => "too many parameters for",
+			ExErr::StartWrongToken => "unexpected token",
+			ExErr::WrongStackLen => "incomplete expression",
+			ExErr::StartOperandMissing | ExErr::GetOperandMissing | ExErr::GetOperandStart => "missing operand for",
+			ExErr::TokenNotFound => "token not found",
+			ExErr::EvalDiv0 => "division by zero in",
+			ExErr::EvalMaxParam | ExErr::EvalAvgParam | ExErr::EvalStatParam => "no parameter for",
+			ExErr::EvalBadParam => "parameter out of range for",
+			ExErr::VariableNotFound => "no value for variable",
+			ExErr::Overflow => "integer overflow in",
+		};
+		write!(f, "{}", err_msg)
+	}
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RpnError {
 	None,
 	AnaLex(LexErr),
@@ -56,19 +117,70 @@ pub enum RpnError {
 }
 impl fmt::Display for RpnError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		let err_msg = match *self {
-			RpnError::AnaLex(_perr) => "rpn analex error",
-			RpnError::Exec(_xerr) => "rpn exec error",
-			RpnError::Parse(_cerr) => "rpn parsing error",
-			RpnError::None => "no error",
-		};
-		write!(f, "{}", err_msg)
+		match *self {
+			RpnError::AnaLex(perr) => write!(f, "{}", perr),
+			RpnError::Exec(xerr) => write!(f, "{}", xerr),
+			RpnError::Parse(cerr) => write!(f, "{}", cerr),
+			RpnError::None => write!(f, "no error"),
+		}
 	}
 }
+impl std::error::Error for RpnError {}
 
+// position of a token in the source of an expression, in characters from the start (end excluded)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+	pub start:usize,
+	pub end:usize,
+}
 
-// evaluation function prototype
-type RpnEvalFn = fn(i:usize, tokens:& Vec<Token>, nb_param:u32) -> Result<EVar, RpnError>;
+// error of an Expression, with the token where it occurs and the position of this token if known
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExprError {
+	pub error:RpnError,
+	pub token:String,
+	pub span:Option<Span>,
+}
+impl ExprError {
+	fn at(error:RpnError, token:&Token) -> ExprError {
+		ExprError{error, token:token.svalue.clone(), span:Some(token.span)}
+	}
+	// column of the error in the source of the expression (starting at 1)
+	pub fn column(&self) -> Option<usize> {
+		self.span.map(|s| s.start+1)
+	}
+}
+impl From<RpnError> for ExprError {
+	fn from(error:RpnError) -> ExprError {
+		ExprError{error, token:String::new(), span:None}
+	}
+}
+impl fmt::Display for ExprError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.error)?;
+		if !self.token.is_empty() {
+			write!(f, " '{}'", self.token)?;
+		}
+		if let Some(col)=self.column() {
+			write!(f, " at column {}", col)?;
+		}
+		Ok(())
+	}
+}
+impl std::error::Error for ExprError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+// evaluation function prototype: the parameters of the operator or function are passed in order
+// (for a binary operator, params[0] is the left operand); their number is params.len()
+pub type RpnEvalFn = fn(params:&[EVar]) -> Result<EVar, RpnError>;
+// prototype of the user functions registered at runtime in an EvalContext; as closures, they can
+// capture the state of the host (device handles, lookup tables, caches...)
+pub type RpnUserFn = Box<dyn Fn(&[EVar]) -> Result<EVar, RpnError>>;
+// prototype of the diagnostics callback of an EvalContext (see set_trace)
+pub type RpnTraceFn = Box<dyn Fn(&str)>;
 
 // catch-all structure defining the characterisitcs of functions, operators and constants
 // this structure is referenced by the struct Token
@@ -78,9 +190,10 @@ pub struct FuCoOpDef {
 	pub params: Option<u32>, // nb of parameters expected (for functions with a fixed number of parameters)
 	pub fn_eval:RpnEvalFn,	//evaluation function, for operators and functions
 	pub val:EVar, // value (for constants only)
+	pub pure:bool, // same parameters always give the same result (operators and functions only)
 }
 
-const NULL_FUCODEF:FuCoOpDef=FuCoOpDef{name:"", fn_eval:eval_null, prio:0, params:None,val:EVar::IVal(0)};
+const NULL_FUCODEF:FuCoOpDef=FuCoOpDef{name:"", fn_eval:eval_null, prio:0, params:None,val:EVar::IVal(0), pure:false};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum TokKind {
@@ -90,9 +203,15 @@ pub enum TokKind {
 	Function,
 	Constant,
 	CString,
+	Variable,
 	Separator,
 	OPar,
 	CPar,
+	// branches, generated for the lazy evaluation of conditions
+	Jump,		// unconditional jump
+	JumpNot,	// condition taken from the value stack, jump if false
+	JumpAnd,	// left operand of '&&' left on the value stack, replaced by false with a jump if false
+	JumpOr,		// left operand of '||' left on the value stack, replaced by true with a jump if true
 }
 
 #[derive(Clone)]
@@ -102,15 +221,17 @@ pub struct Token {
 	svalue: String,
 	pub val: EVar,
 	nb_param: u32,
-	val_consumed: Cell<bool>,
-	fun_exec_done: bool,
 	refdef:&'static FuCoOpDef,
+	slot: usize, // index of the variable slot (variables only)
+	ufn: Option<usize>, // index of the function registered in the context (user functions only)
+	target: usize, // position in the parse stack where to continue (jumps only)
+	span: Span, // position in the source of the expression
 }
 
 impl Token {
 	fn new() -> Token {
 		Token{kind:TokKind::Void, svalue:String::from(""),	val:EVar::IVal(0), unary:false, nb_param:0, 
-		val_consumed:Cell::new(false), fun_exec_done:false,refdef:&NULL_FUCODEF}
+		refdef:&NULL_FUCODEF, slot:0, ufn:None, target:0, span:Span::default()}
 	}
 	fn is_operator(&self) -> bool {
 		match self.kind {
@@ -120,7 +241,7 @@ impl Token {
 	}
 	fn is_operand(&self) -> bool {
 		match self.kind {
-			TokKind::Number | TokKind::Constant | TokKind::CString => return true,
+			TokKind::Number | TokKind::Constant | TokKind::CString | TokKind::Variable => return true,
 			_ => return false
 		}
 	}
@@ -156,6 +277,30 @@ impl Token {
 			_ => return false
 		}
 	}
+	// '?' of a ternary operator waiting for its ':'
+	fn is_ternary_open(&self) -> bool {
+		self.kind == TokKind::Operator && self.refdef.name == TERNARY_OPEN
+	}
+	// ternary operator (after its ':') or function 'if': only one of the branches is evaluated
+	fn is_cond(&self) -> bool {
+		match self.kind {
+			TokKind::Operator => self.refdef.name == TERNARY_CLOSE,
+			TokKind::Function => self.refdef.name == FN_IF && self.ufn.is_none(),
+			_ => false
+		}
+	}
+	// '&&' and '||': the right operand is evaluated only if needed
+	fn is_short_circuit(&self) -> bool {
+		self.kind == TokKind::Operator && !self.unary && (self.refdef.name == OP_AND || self.refdef.name == OP_OR)
+	}
+	// operators with right associativity
+	fn is_right_assoc(&self) -> bool {
+		self.kind == TokKind::Operator && 
+			(self.refdef.name == TERNARY_OPEN || self.refdef.name == TERNARY_CLOSE || self.refdef.name == OP_POW)
+	}
+	fn is_pow(&self) -> bool {
+		self.kind == TokKind::Operator && self.refdef.name == OP_POW
+	}
 	fn set_unary(&mut self, val:bool) -> bool {
 		match self.kind {
 			TokKind::Operator => {
@@ -179,14 +324,84 @@ impl fmt::Display for Token {
 	}
 }
 
-pub struct ITokenVec {
-	pub vec:Vec<Rc<Token>>,
+// user function registered at runtime, by name and number of parameters
+struct UserFn {
+	name:String,
+	params:Option<u32>,
+	fn_eval:RpnUserFn,
 }
 
 // eval context: optional information for an Expression
-// limited to user-defined functions for the moment
+// - user-defined functions, static (user_fns) or registered at runtime (register_fn)
+// - user-defined constants (register_const)
+// - diagnostics callback (set_trace); without it, the library is silent
+// - handling of the integer overflows (set_overflow_promote): an error by default
+// - unit of the angles of the trigonometric functions (set_degrees): radians by default
+#[derive(Default)]
 pub struct EvalContext {
-	pub user_fns:&'static[FuCoOpDef],	
+	pub user_fns:&'static[FuCoOpDef],
+	fns:Vec<UserFn>,
+	consts:Vec<(String, EVar)>,
+	trace:Option<RpnTraceFn>,
+	promote:bool,
+	degrees:bool,
+}
+
+impl EvalContext {
+	pub fn new() -> EvalContext {
+		EvalContext::default()
+	}
+	pub fn with_user_fns(user_fns:&'static[FuCoOpDef]) -> EvalContext {
+		EvalContext{user_fns, ..EvalContext::default()}
+	}
+	// registers a function with its number of parameters (None for a variable number of parameters)
+	// a function already registered with the same name is replaced, and a built-in function of
+	// the same name is hidden
+	pub fn register_fn<F>(&mut self, name:&str, params:Option<u32>, fn_eval:F) 
+		where F:Fn(&[EVar]) -> Result<EVar, RpnError> + 'static {
+		let ufn=UserFn{name:name.to_string(), params, fn_eval:Box::new(fn_eval)};
+		if let Some(idx)=self.fns.iter().position(|f| f.name == name) {
+			self.fns[idx]=ufn;
+		}
+		else {
+			self.fns.push(ufn);
+		}
+	}
+	fn find_fn(&self, name:&str) -> Option<usize> {
+		self.fns.iter().position(|f| f.name == name)
+	}
+	// registers a constant; its value is taken when an expression using it is parsed
+	// a constant already registered with the same name is replaced, and a built-in constant of the same
+	// name (e.g. 'e' or 'h') is hidden
+	pub fn register_const(&mut self, name:&str, val:EVar) {
+		if let Some(co)=self.consts.iter_mut().find(|co| co.0 == name) {
+			co.1=val;
+		}
+		else {
+			self.consts.push((name.to_string(), val));
+		}
+	}
+	fn find_const(&self, name:&str) -> Option<&EVar> {
+		self.consts.iter().find(|co| co.0 == name).map(|co| &co.1)
+	}
+	// sets the callback receiving the diagnostics messages of the expressions using this context
+	// (results of the analysis and of the parsing)
+	pub fn set_trace<F>(&mut self, trace:F) where F:Fn(&str) + 'static {
+		self.trace=Some(Box::new(trace));
+	}
+	// with 'promote', an operation whose integer result overflows is evaluated with floats 
+	// instead of failing with ExErr::Overflow
+	pub fn set_overflow_promote(&mut self, promote:bool) {
+		self.promote=promote;
+	}
+	// with 'degrees', the angles of sin, cos, tan, asin, acos, atan and atan2 are in degrees
+	// NB: the unit is chosen when an expression is parsed
+	pub fn set_degrees(&mut self, degrees:bool) {
+		self.degrees=degrees;
+	}
+	fn promote(context:Option<&EvalContext>) -> bool {
+		context.is_some_and(|c| c.promote)
+	}
 }
 
 // main component of the rpn crate; contains:
@@ -195,42 +410,95 @@ pub struct EvalContext {
 pub struct Expression<'a> {
 	parse_stack:Vec<Token>,
 	tokens:Vec<Token>,
-	itokens:ITokenVec,
-	jtokens:ITokenVec,
 	tokenized:bool,
 	parsed:bool,
-	res:Result<Option<EVar>, RpnError>,
+	res:Result<Option<EVar>, ExprError>,
 	context:Option<&'a EvalContext>,
+	// variables of the expression (names in order of first appearance) and their current value
+	var_names:Vec<String>,
+	var_slots:Vec<Option<EVar>>,
 }
 
 impl <'a> Expression <'a> {
 	pub fn new(exp: &str, eval_context:Option<&'a EvalContext>) -> Expression<'a> {
-		let mut toks=Expression {
-			jtokens:ITokenVec{vec:vec![]},itokens:ITokenVec{vec:vec![]},
-			tokens:vec![],parse_stack:vec![], 
-			tokenized:false, parsed:false, res:Ok(None), context:eval_context};
+		let mut toks=Expression::empty(eval_context);
 		
 		let rv=toks.parse(exp);
-		if rv.is_ok() {
-			println!("expression \'{}\' successully parsed as \'{}\'", exp, toks);
+		if let Err(e)=rv {
+			toks.trace(|| format!("expression \'{}\' could not be parsed with error {}", exp, e));
+			toks.res = Err(e);
 		}
 		else {
-			println!("expression \'{}\' could not be parsed with error {}", exp, rv.err().unwrap());
-			toks.res = Err(rv.err().unwrap());
+			toks.trace(|| format!("expression \'{}\' successully parsed as \'{}\'", exp, toks));
 		}
 		
 		toks
 	}
 	
+	// expression with nothing tokenized or parsed yet
+	fn empty(eval_context:Option<&'a EvalContext>) -> Expression<'a> {
+		Expression {
+			tokens:vec![],parse_stack:vec![], 
+			tokenized:false, parsed:false, res:Ok(None), context:eval_context,
+			var_names:vec![], var_slots:vec![]}
+	}
+	
 	pub fn add_context(&mut self, eval_context:&'a EvalContext){
 		self.context=Some(eval_context);
 	}
 	
+	// diagnostics message, built and sent only if the context has a trace callback
+	fn trace<F>(&self, msg:F) where F:FnOnce() -> String {
+		if let Some(trace)=self.context.and_then(|c| c.trace.as_ref()) {
+			trace(&msg());
+		}
+	}
+	
+	// false if the analysis or the parsing failed, result() giving then the error
+	pub fn is_parsed(&self) -> bool {
+		self.parsed
+	}
+	// error of the analysis or of the parsing
+	pub fn parse_error(&self) -> Option<ExprError> {
+		if self.parsed {None} else {self.res.clone().err()}
+	}
+	
+	// names of the variables of the expression; the position of a name is its slot index
+	pub fn variables(&self) -> &[String] {
+		&self.var_names
+	}
+	pub fn var_slot(&self, name:&str) -> Option<usize> {
+		self.var_names.iter().position(|n| n == name)
+	}
+	// bind a value to a variable slot, kept for all subsequent evaluations until rebound
+	pub fn set_slot(&mut self, slot:usize, val:EVar) -> Result<(), ExprError> {
+		if let Some(s)=self.var_slots.get_mut(slot) {
+			*s=Some(val);
+			return Ok(());
+		}
+		Err(RpnError::Exec(ExErr::VariableNotFound).into())
+	}
+	pub fn set_var(&mut self, name:&str, val:EVar) -> Result<(), ExprError> {
+		match self.var_slot(name) {
+			Some(slot) => self.set_slot(slot, val),
+			None => Err(ExprError{error:RpnError::Exec(ExErr::VariableNotFound), token:name.to_string(), span:None}),
+		}
+	}
+	// error located at the first occurrence of a variable
+	fn var_error(&self, error:RpnError, slot:usize) -> ExprError {
+		match self.tokens.iter().find(|t| t.kind == TokKind::Variable && t.slot == slot) {
+			Some(tok) => ExprError::at(error, tok),
+			None => error.into(),
+		}
+	}
+	
 	pub fn display_parsed(&self) -> String {
 		let mut msg=String::from("");
 		for val in self.parse_stack.iter() {
 			match val.kind {
 				TokKind::Function => msg.push_str(&*format!("{}({}) ", val.svalue, val.nb_param)),
+				TokKind::Jump | TokKind::JumpNot | TokKind::JumpAnd | TokKind::JumpOr => 
+					msg.push_str(&*format!("{}({}) ", val.svalue, val.target)),
 				TokKind::Operator => msg.push_str(&*format!("{}({}) ", val.svalue, 
 					if val.unary {"un"} else {"bi"})),
 				_ => msg.push_str(&*format!("{} ", val.svalue)),
@@ -249,14 +517,7 @@ impl <'a> Expression <'a> {
 		match &self.res {
 			Ok(oval) => {
 				match oval {
-					Some(val) => {
-						match val {
-							EVar::BVal(b) => format!("{}", b),
-							EVar::FVal(f) => format!("{}", f),
-							EVar::IVal(i) => format!("{}", i),
-							EVar::SVal(s) => format!("{}", s),
-						}
-					}
+					Some(val) => val.to_string(),
 					None => "".to_string(),
 				}
 			},
@@ -264,27 +525,52 @@ impl <'a> Expression <'a> {
 		}
 	}
 	// fn tokenize generates a list of token from an expression (param 'exp')
-	pub fn tokenize(&mut self, exp: &str) -> Result<(), RpnError> {
+	pub fn tokenize(&mut self, exp: &str) -> Result<(), ExprError> {
 		let mut exp_iter=exp.chars();
 		let mut resok=true;
-		let mut error=RpnError::None;
+		let mut error=ExprError::from(RpnError::None);
+		// position in characters of the analysis
+		let mut pos=0;
 		self.tokens.clear();
+		self.var_names.clear();
+		self.var_slots.clear();
 		loop {
+			// the spaces before a token are skipped here, so that its starting position is known
+			let rest=exp_iter.as_str();
+			let trimmed=rest.trim_start_matches(|c:char| c.is_control() || c.is_whitespace());
+			pos += rest[..rest.len()-trimmed.len()].chars().count();
+			exp_iter=trimmed.chars();
 			let rv=self.get_token(&mut exp_iter);
+			let len=trimmed[..trimmed.len()-exp_iter.as_str().len()].chars().count();
+			let span=Span{start:pos, end:pos+len};
+			pos += len;
 			match rv {
 				Ok(v) => {
 					if v.is_none() {
 						break;
 					}
 					else {
-						self.tokens.push(v.unwrap());
-						self.itokens.vec.push(Rc::new(self.tokens.last().unwrap().clone()));
+						let mut tok=v.unwrap();
+						tok.span=span;
+						if tok.kind == TokKind::Variable {
+							// each distinct variable name gets its own slot
+							tok.slot=match self.var_slot(&tok.svalue) {
+								Some(slot) => slot,
+								None => {
+									self.var_names.push(tok.svalue.clone());
+									self.var_slots.push(None);
+									self.var_names.len()-1
+								}
+							};
+						}
+						self.tokens.push(tok);
 					}
 				},
 				Err(e) => {
-					println!("get_token error {}", e);
+					// the error is located from the start of the token to the character which could not be analyzed
+					let bad:String=trimmed.chars().take(len.max(1)).collect();
 					resok=false;
-					error=e;
+					error=ExprError{error:e, span:Some(Span{start:span.start, end:span.start+bad.chars().count()}), token:bad};
 					break;
 				}
 			}		
@@ -292,30 +578,26 @@ impl <'a> Expression <'a> {
 		if resok {
 			self.tokenized=true;
 			self.res=Ok(None);
-			println!("expression \'{}\' successully tokenized as {}", exp, self.display_tokenized());
+			self.trace(|| format!("expression \'{}\' successully tokenized as {}", exp, self.display_tokenized()));
 			return Ok(());
 		}
-		println!("expression \'{}\' could not be tokenized", exp);
+		self.trace(|| format!("expression \'{}\' could not be tokenized with error {}", exp, error));
 		self.tokenized=false;
 		self.res=Err(error.clone());
 		Err(error)
 	}
 	// fn parse creates a rpn stack from the expression passed as parameter
 	// the "parsed" rpn stack is then ready for evaluation
-	pub fn parse(&mut self, exp: &str) -> Result<(), RpnError>  {
+	pub fn parse(&mut self, exp: &str) -> Result<(), ExprError>  {
 		self.parse_stack.clear();
 		let mut op_stack:Vec<Token> = vec![];	
 		let mut resok=true;
 		let mut prev_tok_kind=TokKind::Void;
-		let mut error=RpnError::None;
+		let mut error=ExprError::from(RpnError::None);
 		if !self.tokenized {
 			self.tokenize(exp)?;
 		}
-		for rv in &self.itokens.vec {
-			//self.jtokens.vec.push(rv.clone());
-			self.jtokens.vec.push(Rc::clone(rv));
-		}
-		for rv in &self.tokens {
+		for (itok, rv) in self.tokens.iter().enumerate() {
 			//self.rtokens.vec.push(&rv);
 			let mut nb_param;
 			let mut tok=rv.clone();
@@ -324,13 +606,15 @@ impl <'a> Expression <'a> {
 			if tok.is_operand() {
 				// this is an operand, to be stacked directly in the parse stack
 				self.parse_stack.push(tok);
-				if let Some(last_op)=op_stack.last() {
-					// if in addition the last element of the operators stack is an unary operator, it is
-					// moved to the parse stack
-					if last_op.is_unary() {
-						self.parse_stack.push(last_op.clone());
-						op_stack.pop();
+				// if in addition the last elements of the operators stack are unary operators, they are
+				// moved to the parse stack, unless the operand is the base of a power (-x**2 is -(x**2))
+				let before_pow=self.tokens.get(itok+1).is_some_and(|t| t.is_pow());
+				while let Some(last_op)=op_stack.last() {
+					if !last_op.is_unary() || before_pow {
+						break;
 					}
+					self.parse_stack.push(last_op.clone());
+					op_stack.pop();
 				}
 			}
 			else {
@@ -345,11 +629,18 @@ impl <'a> Expression <'a> {
 						// to movve in the operator stack, after de-stacking operators to the parse stack
 						if op_stack.len()==0 {
 							// error, you cannot have a separator as first element in the operator stack
-							error=RpnError::Parse(ParErr::ParamSep);
+							resok=false;
+							error=ExprError::at(RpnError::Parse(ParErr::ParamSep), &tok);
 							break;
 						}
 						else {
 							while let Some(last_op)=op_stack.last() {
+								if last_op.is_ternary_open() {
+									// a '?' without its ':' in the parameter
+									resok=false;
+									error=ExprError::at(RpnError::Parse(ParErr::MatchingTernary), last_op);
+									break;
+								}
 								if last_op.is_operator() {
 									self.parse_stack.push(last_op.clone());
 									op_stack.pop();
@@ -358,9 +649,48 @@ impl <'a> Expression <'a> {
 									break;
 								}
 							}
+							if !resok {
+								break;
+							}
 						}
 						op_stack.push(tok);
 					},
+					TokKind::Operator if tok.refdef.name == TERNARY_CLOSE => {
+						// ':' of a ternary operator: the operators of its first branch are transferred to the 
+						// parse stack until the matching '?' is found, which is then replaced by the
+						// ternary operator itself (three operands)
+						// both branches must be present: not right after the '?', nor at the end of the expression,
+						// of a parameter or of a parenthesis
+						let empty_first=itok == 0 || self.tokens[itok-1].is_ternary_open();
+						let empty_second=self.tokens.get(itok+1).is_none_or(|t|
+							t.kind == TokKind::CPar || t.kind == TokKind::Separator || (t.kind == TokKind::Operator && t.refdef.name == TERNARY_CLOSE));
+						if empty_first || empty_second {
+							resok=false;
+							error=ExprError::at(RpnError::Parse(ParErr::MatchingTernary), &tok);
+							break;
+						}
+						let mut match_found=false;
+						while let Some(last_op)=op_stack.last() {
+							if last_op.is_ternary_open() {
+								op_stack.pop();
+								match_found=true;
+								break;
+							}
+							if last_op.is_generic_sep() {
+								break;
+							}
+							self.parse_stack.push(last_op.clone());
+							op_stack.pop();
+						}
+						if !match_found {
+							resok=false;
+							error=ExprError::at(RpnError::Parse(ParErr::MatchingTernary), &tok);
+							break;
+						}
+						tok.svalue=String::from(TERNARY_OPEN)+TERNARY_CLOSE;
+						tok.nb_param=3;
+						op_stack.push(tok);
+					},
 					TokKind::Operator => {
 						// new token is an operator
 						// if this is the first, stack it aside
@@ -368,6 +698,12 @@ impl <'a> Expression <'a> {
 						// if the previous token put aside is a function or opening parenthesis, or
 						// if the previous token is an operator with a lower precedence, the new operator
 						// is stacked aside
+						let unary=matches!(prev_tok_kind, TokKind::Void | TokKind::Separator | TokKind::Operator | TokKind::OPar);
+						if unary && !UNARY_OPERATORS.contains(&tok.refdef.name) {
+							resok=false;
+							error=ExprError::at(RpnError::Parse(ParErr::MissingOperand), &tok);
+							break;
+						}
 						if op_stack.len()==0 {
 							match prev_tok_kind {
 								TokKind::Void => tok.set_unary(true),
@@ -383,6 +719,8 @@ impl <'a> Expression <'a> {
 								_ => tok.set_unary(false),
 							};
 							match last_op.kind {
+								// an unary operator applies to what follows it
+								_ if tok.is_unary() => to_stack_direct=true,
 								TokKind::OPar |TokKind::Function => {
 									to_stack_direct=true;
 								},
@@ -392,12 +730,15 @@ impl <'a> Expression <'a> {
 									}
 								},
 							};
+							// a right-associative operator does not de-stack the operators of same priority
+							let prio_min=if tok.is_right_assoc() {tok.refdef.prio+1} else {tok.refdef.prio};
 							if !to_stack_direct {
 								while let Some(last_op)=op_stack.last() {
 									if last_op.is_generic_sep() {
 										break;
 									}
-									if (last_op.refdef.prio>0 && last_op.refdef.prio >= tok.refdef.prio) || last_op.is_unary(){
+									// the unary operators before the base of a power are applied to the power
+									if (last_op.refdef.prio>0 && last_op.refdef.prio >= prio_min) || (last_op.is_unary() && !tok.is_pow()) {
 										self.parse_stack.push(last_op.clone());
 										op_stack.pop();
 									}
@@ -428,6 +769,10 @@ impl <'a> Expression <'a> {
 								match_found=true;
 								break;
 							}
+							else if last_op.is_ternary_open() {
+								// a '?' without its ':' inside the parentheses
+								break;
+							}
 							else {
 								if last_op.is_param_sep() {
 									nb_param += 1;
@@ -442,17 +787,20 @@ impl <'a> Expression <'a> {
 						if !match_found {
 							// the matching opening parenthesis was not found, this is an error
 							resok=false;
-							error=RpnError::Parse(ParErr::MatchingPar);
+							error=match op_stack.last() {
+								Some(last_op) => ExprError::at(RpnError::Parse(ParErr::MatchingTernary), last_op),
+								None => ExprError::at(RpnError::Parse(ParErr::MatchingPar), &tok),
+							};
 							break;
 						}
 						if let Some(last_op)=op_stack.last() {
 							if last_op.is_function() {
 								let mut op=last_op.clone();
 								op.nb_param=nb_param;
-								if let Some(params)=op.refdef.params {
+								if let Some(params)=self.expected_params(&op) {
 									if params != nb_param {
 										resok=false;
-										error=RpnError::Parse(ParErr::ParamNb);
+										error=ExprError::at(RpnError::Parse(ParErr::ParamNb), &op);
 										break;
 									}
 								}
@@ -472,7 +820,13 @@ impl <'a> Expression <'a> {
 				if last_op.is_opar() {
 					// should not happen, this is an orphan parenthesis
 					resok=false;
-					error=RpnError::Parse(ParErr::MatchingPar);
+					error=ExprError::at(RpnError::Parse(ParErr::MatchingPar), last_op);
+					break;
+				}
+				if last_op.is_ternary_open() {
+					// a '?' without its ':'
+					resok=false;
+					error=ExprError::at(RpnError::Parse(ParErr::MatchingTernary), last_op);
 					break;
 				}
 				self.parse_stack.push(last_op.clone());
@@ -480,97 +834,248 @@ impl <'a> Expression <'a> {
 			}
 		}
 		
+		if resok {
+			// the functions named without parentheses (e.g. 'sqrt' alone) have no parameter
+			if let Some(tok)=self.parse_stack.iter().find(|t| t.is_function() && self.expected_params(t).is_some_and(|n| n != t.nb_param)) {
+				resok=false;
+				error=ExprError::at(RpnError::Parse(ParErr::ParamNb), tok);
+			}
+		}
 		if resok {
 			//println!("{}", parse_stack);
+			self.fold_constants();
+			self.link_branches();
 			self.parsed=true;
 			self.res=Ok(None);
 			return Ok(());
 		}
-		println!("parse stack {}", self.display_parsed());
+		self.trace(|| format!("parse stack {}", self.display_parsed()));
 		self.parsed=false;
 		self.res=Err(error.clone());
 		Err(error)
 	}
-	pub fn eval(&mut self) -> Result<Option<EVar>, RpnError> {
-		if !self.parsed {
-			//self.parse(exp)?;
-			if self.res.is_ok() {
-				//self.compile
+	// number of parameters expected by a function, None if variable
+	fn expected_params(&self, tok:&Token) -> Option<u32> {
+		if let Some(idx)=tok.ufn {
+			if let Some(context)=self.context {
+				return context.fns[idx].params;
 			}
-			else {
-				return Err(RpnError::Exec(ExErr::NotParsed));
-			}
-		}
-		self.res=Ok(None);
-		// reset exec flags of all tokens in the stack
-		for val in &mut self.parse_stack {
-			val.val_consumed.set(false);
-			val.fun_exec_done = false;
-		}
-		// find the position of the first operator or non-zero param function
-		let mut istart = self.parse_stack.iter().position(|i| (i.kind==TokKind::Operator) || 
-			(i.kind==TokKind::Function && i.nb_param>0));
-		// no operator nor function, meaning the stack must have one element (number, constant or zero-param function)
-		// and the result for the evaluation is the value of this single element
-		if istart.is_none() {
-			if self.parse_stack.len() == 1 {
-				if let Some(op)=self.parse_stack.get(0) {
-					match op.kind {
-						TokKind::Number | /*TokKind::Operator |*/ TokKind::Constant => self.res=Ok(Some(op.val.clone())),
-						TokKind::Function => {
-							if op.nb_param>0 {
-								self.res=Err(RpnError::Exec(ExErr::TooManyParams))
-							}
-							else {
-								let rv=(op.refdef.fn_eval)(0, & self.parse_stack, 0);
-								if rv.is_ok() {
-									self.res=Ok(Some(rv.ok().unwrap()));
-								}
-								else {
-									self.res=Err(rv.err().unwrap());
-								}
-							}
+		}
+		tok.refdef.params
+	}
+	// constant folding: the sub-expressions made only of numbers, constants and strings combined by pure
+	// operators and functions are evaluated once, and replaced in the parse stack by their value
+	// a sub-expression whose evaluation fails (e.g. division by 0) is kept, the error occurs at evaluation
+	fn fold_constants(&mut self) {
+		let mut folded:Vec<Token>=Vec::with_capacity(self.parse_stack.len());
+		// for each value on the stack of the evaluation: position in 'folded' of the first token 
+		// of the sub-expression giving this value, and whether this sub-expression is constant
+		let mut values:Vec<(usize, bool)>=vec![];
+		for tok in self.parse_stack.drain(..) {
+			match tok.kind {
+				TokKind::Operator | TokKind::Function => {
+					// a token without all its operands (e.g. '2*') is not folded, the error occurs at evaluation
+					let complete=values.len() >= tok.nb_param as usize;
+					let ipar=values.len()-(tok.nb_param as usize).min(values.len());
+					let start=if let Some(v)=values.get(ipar) {v.0} else {folded.len()};
+					let mut is_const=complete && tok.refdef.pure && values[ipar..].iter().all(|v| v.1);
+					values.truncate(ipar);
+					if is_const {
+						// each constant parameter is a single token at this point
+						let params:Vec<EVar>=folded[start..].iter().map(|t| t.val.clone()).collect();
+						if let Ok(val)=(tok.refdef.fn_eval)(&params) {
+							let mut ctok=Token::new();
+							ctok.kind=match val {
+								EVar::SVal(_) => TokKind::CString,
+								_ => TokKind::Number,
+							};
+							ctok.svalue=format!("{}", val);
+							ctok.val=val;
+							// the constant covers the whole sub-expression
+							ctok.span=folded[start..].iter().fold(tok.span, |span, t| 
+								Span{start:span.start.min(t.span.start), end:span.end.max(t.span.end)});
+							folded.truncate(start);
+							folded.push(ctok);
+							values.push((start, true));
+							continue;
 						}
-						_ => self.res=Err(RpnError::Exec(ExErr::StartWrongToken)),
+						is_const=false;
 					}
+					folded.push(tok);
+					values.push((start, is_const));
+				},
+				TokKind::Variable => {
+					values.push((folded.len(), false));
+					folded.push(tok);
+				},
+				_ => {
+					values.push((folded.len(), true));
+					folded.push(tok);
+				},
+			}
+		}
+		self.parse_stack=folded;
+	}
+	// lazy evaluation: the parameters of the conditions (ternary operator and 'if') and of the '&&'/'||' 
+	// operators are reorganized in the parse stack with jumps, so that only the necessary ones are evaluated
+	//   c ? a : b   =>  c jz(L1) a jmp(L2) L1: b L2:
+	//   a && b      =>  a jand(L) b && L:
+	//   a || b      =>  a jor(L) b || L:
+	fn link_branches(&mut self) {
+		if !self.parse_stack.iter().any(|t| t.is_cond() || t.is_short_circuit()) {
+			return;
+		}
+		// start position of the sub-expression ending with each token of the parse stack
+		let mut starts:Vec<usize>=Vec::with_capacity(self.parse_stack.len());
+		let mut values:Vec<usize>=vec![];
+		for (i, tok) in self.parse_stack.iter().enumerate() {
+			let mut start=i;
+			if tok.kind == TokKind::Operator || tok.kind == TokKind::Function {
+				let ipar=values.len()-(tok.nb_param as usize).min(values.len());
+				if let Some(&v)=values.get(ipar) {
+					start=v;
 				}
+				values.truncate(ipar);
+			}
+			values.push(start);
+			starts.push(start);
+		}
+		let tokens=std::mem::take(&mut self.parse_stack);
+		// the sub-expressions at the top level (only one if the expression is well-formed)
+		let mut roots:Vec<usize>=vec![];
+		let mut end=tokens.len();
+		while end > 0 {
+			roots.push(end-1);
+			end=starts[end-1];
+		}
+		for root in roots.iter().rev() {
+			Expression::emit_branches(&tokens, &starts, *root, &mut self.parse_stack);
+		}
+	}
+	fn emit_branches(tokens:&[Token], starts:&[usize], end:usize, out:&mut Vec<Token>) {
+		let tok=&tokens[end];
+		// parameters of the token: the sub-expressions ending just before it, up to its own start
+		let mut params:Vec<usize>=vec![];
+		let mut i=end;
+		while i > starts[end] {
+			params.push(i-1);
+			i=starts[i-1];
+		}
+		params.reverse();
+		let jump=|kind:TokKind, name:&str| {
+			let mut jtok=Token::new();
+			jtok.kind=kind;
+			jtok.svalue=String::from(name);
+			jtok.span=tok.span;
+			jtok
+		};
+		if tok.is_cond() && params.len() == 3 {
+			Expression::emit_branches(tokens, starts, params[0], out);
+			let jnot=out.len();
+			out.push(jump(TokKind::JumpNot, "jz"));
+			Expression::emit_branches(tokens, starts, params[1], out);
+			let jend=out.len();
+			out.push(jump(TokKind::Jump, "jmp"));
+			out[jnot].target=out.len();
+			Expression::emit_branches(tokens, starts, params[2], out);
+			out[jend].target=out.len();
+		}
+		else if tok.is_short_circuit() && params.len() == 2 {
+			Expression::emit_branches(tokens, starts, params[0], out);
+			let jsc=out.len();
+			if tok.refdef.name == OP_AND {
+				out.push(jump(TokKind::JumpAnd, "jand"));
 			}
 			else {
-				self.res=Err(RpnError::Exec(ExErr::WrongStackLen));			
+				out.push(jump(TokKind::JumpOr, "jor"));
 			}
+			Expression::emit_branches(tokens, starts, params[1], out);
+			out.push(tok.clone());
+			out[jsc].target=out.len();
 		}
-		// impossible (that would mean an operator or a 1+ param function without operands)
-		else if istart == Some(0) {
-			self.res=Err(RpnError::Exec(ExErr::StartOperandMissing));
+		else {
+			for param in params {
+				Expression::emit_branches(tokens, starts, param, out);
+			}
+			out.push(tok.clone());
 		}
-		else { while let Some(i)=istart {
-			if let Some(op)=self.parse_stack.get(i) {
-				let rv=(op.refdef.fn_eval)(i, &self.parse_stack, op.nb_param);
-				if let Ok(res) = rv {
-					self.res=Ok(Some(res));
-				}
-				else {
-					self.res=Err(rv.err().unwrap());
-					break;
-				}
-				if let Some(op)=self.parse_stack.get_mut(i) {
-					op.fun_exec_done=true;
-					if let Ok(Some(val))=&self.res {
-						op.val=val.clone();
-					}
-				}
-			} 
+	}
+	// evaluation of an expression, the values of its variables being looked up by name in 'vars'
+	// the values are kept in the variable slots for subsequent calls to eval
+	pub fn eval_with(&mut self, vars:&HashMap<String, EVar>) -> Result<Option<EVar>, ExprError> {
+		for (slot, name) in self.var_names.iter().enumerate() {
+			if let Some(v)=vars.get(name) {
+				self.var_slots[slot]=Some(v.clone());
+			}
 			else {
-				self.res=Err(RpnError::Exec(ExErr::TokenNotFound));	
-				break;
+				self.res=Err(self.var_error(RpnError::Exec(ExErr::VariableNotFound), slot));
+				return self.res.clone();
+			}
+		}
+		self.eval()
+	}
+	// evaluation of an expression with the values currently bound to its variable slots
+	pub fn eval(&mut self) -> Result<Option<EVar>, ExprError> {
+		if !self.parsed {
+			//self.parse(exp)?;
+			if self.res.is_ok() {
+				//self.compile
 			}
-			// look next operator or function 
-			istart = self.parse_stack.iter().position(|i| !i.fun_exec_done && ((i.kind==TokKind::Operator) || 
-				(i.kind==TokKind::Function && i.nb_param>0)));
-			
-		}}
+			else {
+				// the error of the analysis or of the parsing tells why the expression is not parsed
+				return self.res.clone();
+			}
+		}
+		self.res=self.exec().map(Some);
 		self.res.clone()
 	}
+	// function registered at runtime in the context of the expression
+	fn user_fn(&self, idx:usize) -> Result<&RpnUserFn, RpnError> {
+		if let Some(context)=self.context {
+			if let Some(ufn)=context.fns.get(idx) {
+				return Ok(&ufn.fn_eval);
+			}
+		}
+		Err(RpnError::Exec(ExErr::EvalNullFn))
+	}
+	// stack machine: the parse stack is walked once, operands are pushed on a value stack, and
+	// operators and functions replace their parameters on top of the value stack by their result
+	fn exec(&self) -> Result<EVar, ExprError> {
+		let mut stack:Vec<EVar>=Vec::with_capacity(self.parse_stack.len());
+		let promote=EvalContext::promote(self.context);
+		let mut pc=0;
+		while let Some(tok)=self.parse_stack.get(pc) {
+			pc += 1;
+			match tok.kind {
+				TokKind::Number | TokKind::Constant | TokKind::CString => stack.push(tok.val.clone()),
+				TokKind::Variable => {
+					if let Some(Some(v))=self.var_slots.get(tok.slot) {
+						stack.push(v.clone());
+					}
+					else {
+						return Err(ExprError::at(RpnError::Exec(ExErr::VariableNotFound), tok));
+					}
+				},
+				TokKind::Function if tok.ufn.is_some() => {
+					let fn_eval=self.user_fn(tok.ufn.unwrap()).map_err(|e| ExprError::at(e, tok))?;
+					exec_fn(fn_eval, self.expected_params(tok), tok.nb_param as usize, &mut stack, promote).map_err(|e| ExprError::at(e, tok))?;
+				},
+				TokKind::Operator | TokKind::Function => 
+					exec_fn(&tok.refdef.fn_eval, tok.refdef.params, tok.nb_param as usize, &mut stack, promote).map_err(|e| ExprError::at(e, tok))?,
+				TokKind::Jump | TokKind::JumpNot | TokKind::JumpAnd | TokKind::JumpOr => {
+					if exec_jump(tok.kind, &mut stack).map_err(|e| ExprError::at(e, tok))? {
+						pc=tok.target;
+					}
+				},
+				_ => return Err(ExprError::at(RpnError::Exec(ExErr::StartWrongToken), tok)),
+			}
+		}
+		// a well-formed expression leaves exactly one value, its result
+		if stack.len() != 1 {
+			return Err(RpnError::Exec(ExErr::WrongStackLen).into());
+		}
+		Ok(stack.pop().unwrap())
+	}
 }
 
 impl <'a> fmt::Display for Expression <'a> {
@@ -596,10 +1101,19 @@ enum Lex {
 	NumExpVal,
 	NumHex,
 	CString,
+	CStringEsc,
+	CStringUniOpen,
+	CStringUni,
 	Operator,
 	FuncConst,
 }
 
+impl Lex {
+	fn is_string(&self) -> bool {
+		*self == Lex::CString || *self == Lex::CStringEsc || *self == Lex::CStringUniOpen || *self == Lex::CStringUni
+	}
+}
+
 // enm for number analysis
 enum NumType{
 	Integer,
@@ -610,6 +1124,10 @@ enum NumType{
 // special characters for the lexical analysis
 // double quote
 const CHAR_QUOTE:char='\u{0022}'; 
+// single quote, alternative delimiter for strings
+const CHAR_APOS:char='\u{0027}';
+// escape character in strings
+const CHAR_ESC:char='\\';
 const CHAR_OPAR:char='(';
 const CHAR_CPAR:char=')';
 // plus and minus, not as operator but signs for a float exponent; +/- in front of a number are managed
@@ -617,96 +1135,211 @@ const CHAR_CPAR:char=')';
 const CHAR_PLUS:char='+'; 
 const CHAR_MINUS:char='-';
 const CHAR_SEP:char=',';
+const CHAR_QMARK:char='?';
+const CHAR_COLON:char=':';
 const CHAR_SP:char=' ';
 const CHAR_DECPOINT:char='.';
+const CHAR_UNDERSCORE:char='_';
+const CHAR_DOLLAR:char='$';
 
 // characters for an hexadecimal number
 const HEXA_CHARS:&'static str="0123456789abcdefABCDEF";
 // characters for operators
-const OP_CHARS:&'static str="+-/*!^&=|<>~";
+const OP_CHARS:&'static str="+-/*%!^&=|<>~";
+
+// names of the operators and functions with a lazy evaluation of their parameters
+const TERNARY_OPEN:&'static str="?";
+const TERNARY_CLOSE:&'static str=":";
+const OP_AND:&'static str="&&";
+const OP_OR:&'static str="||";
+const FN_IF:&'static str="if";
+// power operator, right-associative and binding tighter than the unary operators
+const OP_POW:&'static str="**";
+// operators which can be unary, the others need a left operand
+const UNARY_OPERATORS:[&'static str;4]=["+", "-", "!", "~"];
 
 // operators defintion array 
-const OPERATORS:[&'static FuCoOpDef;19]=[
-	&FuCoOpDef{name:"+",  prio:9,  fn_eval:eval_plus,   val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"-",  prio:10, fn_eval:eval_sub,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"*",  prio:12, fn_eval:eval_mul,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"/",  prio:11, fn_eval:eval_div,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"==", prio:5,  fn_eval:eval_eq,     val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"!=", prio:5,  fn_eval:eval_neq,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"<=", prio:5,  fn_eval:eval_infeq,  val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:">=", prio:5,  fn_eval:eval_supeq,  val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"<",  prio:5,  fn_eval:eval_inf,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:">",  prio:5,  fn_eval:eval_sup,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"!",  prio:13, fn_eval:eval_lognot, val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"~",  prio:13, fn_eval:eval_bitnot, val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"&&", prio:2,  fn_eval:eval_and,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"||", prio:2,  fn_eval:eval_or,     val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"^",  prio:3,  fn_eval:eval_bitxor, val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"&",  prio:3,  fn_eval:eval_band,   val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"|",  prio:3,  fn_eval:eval_bor,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:"<<", prio:1,  fn_eval:eval_shl,    val:EVar::IVal(0), params:None},
-	&FuCoOpDef{name:">>", prio:1,  fn_eval:eval_shr,    val:EVar::IVal(0), params:None},
+const OPERATORS:[&'static FuCoOpDef;24]=[
+	&FuCoOpDef{name:"+",  prio:10, fn_eval:eval_plus,   val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"-",  prio:11, fn_eval:eval_sub,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"*",  prio:13, fn_eval:eval_mul,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"/",  prio:13, fn_eval:eval_div,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"%",  prio:13, fn_eval:eval_rem,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"//", prio:13, fn_eval:eval_floordiv,val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"**", prio:15, fn_eval:eval_pow,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"==", prio:6,  fn_eval:eval_eq,     val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"!=", prio:6,  fn_eval:eval_neq,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"<=", prio:6,  fn_eval:eval_infeq,  val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:">=", prio:6,  fn_eval:eval_supeq,  val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"<",  prio:6,  fn_eval:eval_inf,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:">",  prio:6,  fn_eval:eval_sup,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"!",  prio:14, fn_eval:eval_lognot, val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"~",  prio:14, fn_eval:eval_bitnot, val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"&&", prio:3,  fn_eval:eval_and,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"||", prio:3,  fn_eval:eval_or,     val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"^",  prio:4,  fn_eval:eval_bitxor, val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"&",  prio:4,  fn_eval:eval_band,   val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"|",  prio:4,  fn_eval:eval_bor,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"<<", prio:2,  fn_eval:eval_shl,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:">>", prio:2,  fn_eval:eval_shr,    val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:"?",  prio:1,  fn_eval:eval_if,     val:EVar::IVal(0), params:None, pure:true},
+	&FuCoOpDef{name:":",  prio:1,  fn_eval:eval_if,     val:EVar::IVal(0), params:None, pure:true},
 ];
 
 // functions defintion array 
-const FUDEF:[&'static FuCoOpDef;13]=[
-	&FuCoOpDef{name:"sin",  fn_eval:eval_sin,  params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"cos",  fn_eval:eval_cos,  params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"tan",  fn_eval:eval_tan,  params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"pow",  fn_eval:eval_pow,  params:Some(2), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"sqrt", fn_eval:eval_sqrt, params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"cbrt", fn_eval:eval_cbrt, params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"exp",  fn_eval:eval_exp,  params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"ln",   fn_eval:eval_ln,   params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"log10",fn_eval:eval_log10,params:Some(1), prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"max",  fn_eval:eval_max,  params:None,    prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"min",  fn_eval:eval_min,  params:None,    prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"avg",  fn_eval:eval_avg,  params:None,    prio:0, val:EVar::IVal(0)},
-	&FuCoOpDef{name:"now",  fn_eval:eval_now,  params:Some(0), prio:0, val:EVar::IVal(0)},
+const FUDEF:[&'static FuCoOpDef;85]=[
+	&FuCoOpDef{name:"sin",  fn_eval:eval_sin,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"cos",  fn_eval:eval_cos,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"tan",  fn_eval:eval_tan,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"asin", fn_eval:eval_asin, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"acos", fn_eval:eval_acos, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"atan", fn_eval:eval_atan, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"atan2",fn_eval:eval_atan2,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"sinh", fn_eval:eval_sinh, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"cosh", fn_eval:eval_cosh, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"tanh", fn_eval:eval_tanh, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"asinh",fn_eval:eval_asinh,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"acosh",fn_eval:eval_acosh,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"atanh",fn_eval:eval_atanh,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"deg",  fn_eval:eval_deg,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"rad",  fn_eval:eval_rad,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"pow",  fn_eval:eval_pow,  params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"sqrt", fn_eval:eval_sqrt, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"cbrt", fn_eval:eval_cbrt, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"exp",  fn_eval:eval_exp,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"ln",   fn_eval:eval_ln,   params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"log10",fn_eval:eval_log10,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"log",  fn_eval:eval_log,  params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"log2", fn_eval:eval_log2, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"hypot",fn_eval:eval_hypot,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"max",  fn_eval:eval_max,  params:None,    prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"min",  fn_eval:eval_min,  params:None,    prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"avg",  fn_eval:eval_avg,  params:None,    prio:0, val:EVar::IVal(0), pure:true},
+	// statistics
+	&FuCoOpDef{name:"sum",       fn_eval:eval_sum,       params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"prod",      fn_eval:eval_prod,      params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"count",     fn_eval:eval_count,     params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"median",    fn_eval:eval_median,    params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"variance",  fn_eval:eval_variance,  params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"stddev",    fn_eval:eval_stddev,    params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"range",     fn_eval:eval_range,     params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"mode",      fn_eval:eval_mode,      params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"percentile",fn_eval:eval_percentile,params:None, prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"now",  fn_eval:eval_now,  params:Some(0), prio:0, val:EVar::IVal(0), pure:false},
+	&FuCoOpDef{name:"if",   fn_eval:eval_if,   params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	// bits and integers
+	&FuCoOpDef{name:"bit",      fn_eval:eval_bit,      params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"setbit",   fn_eval:eval_setbit,   params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"clrbit",   fn_eval:eval_clrbit,   params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"bits",     fn_eval:eval_bits,     params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"popcount", fn_eval:eval_popcount, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"rotl",     fn_eval:eval_rotl,     params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"rotr",     fn_eval:eval_rotr,     params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"bswap16",  fn_eval:eval_bswap16,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"bswap32",  fn_eval:eval_bswap32,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"bswap64",  fn_eval:eval_bswap64,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"gcd",      fn_eval:eval_gcd,      params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"lcm",      fn_eval:eval_lcm,      params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"factorial",fn_eval:eval_factorial,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	// registers
+	&FuCoOpDef{name:"f32_from_regs",     fn_eval:eval_f32_regs,     params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"f32_from_regs_cdab",fn_eval:eval_f32_regs_cdab,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"f32_from_regs_badc",fn_eval:eval_f32_regs_badc,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"f32_from_regs_dcba",fn_eval:eval_f32_regs_dcba,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"i32_from_regs",     fn_eval:eval_i32_regs,     params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"i32_from_regs_cdab",fn_eval:eval_i32_regs_cdab,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"i32_from_regs_badc",fn_eval:eval_i32_regs_badc,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"i32_from_regs_dcba",fn_eval:eval_i32_regs_dcba,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"u32_from_regs",     fn_eval:eval_u32_regs,     params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"u32_from_regs_cdab",fn_eval:eval_u32_regs_cdab,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"u32_from_regs_badc",fn_eval:eval_u32_regs_badc,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"u32_from_regs_dcba",fn_eval:eval_u32_regs_dcba,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"u16",               fn_eval:eval_u16,          params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"i16",               fn_eval:eval_i16,          params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"bcd",               fn_eval:eval_bcd,          params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	// rounding and sign; integers give integers
+	&FuCoOpDef{name:"abs",  fn_eval:eval_abs,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"sign", fn_eval:eval_sign, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"round",fn_eval:eval_round,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"floor",fn_eval:eval_floor,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"ceil", fn_eval:eval_ceil, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"trunc",fn_eval:eval_trunc,params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"frac", fn_eval:eval_frac, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"clamp",fn_eval:eval_clamp,params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	// strings
+	&FuCoOpDef{name:"len",       fn_eval:eval_len,       params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"upper",     fn_eval:eval_upper,     params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"lower",     fn_eval:eval_lower,     params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"trim",      fn_eval:eval_trim,      params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"substr",    fn_eval:eval_substr,    params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"find",      fn_eval:eval_find,      params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"replace",   fn_eval:eval_replace,   params:Some(3), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"startswith",fn_eval:eval_startswith,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"endswith",  fn_eval:eval_endswith,  params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
+	// null values
+	&FuCoOpDef{name:"isnull",    fn_eval:eval_isnull,    params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"coalesce",  fn_eval:eval_coalesce,  params:None,    prio:0, val:EVar::IVal(0), pure:true},
+	];
+
+// trigonometric functions with angles in degrees, replacing those of FUDEF when the context
+// of the expression is in degrees (see EvalContext::set_degrees)
+const TRIG_DEG:[&'static FuCoOpDef;7]=[
+	&FuCoOpDef{name:"sin",  fn_eval:eval_sin_deg,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"cos",  fn_eval:eval_cos_deg,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"tan",  fn_eval:eval_tan_deg,  params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"asin", fn_eval:eval_asin_deg, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"acos", fn_eval:eval_acos_deg, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"atan", fn_eval:eval_atan_deg, params:Some(1), prio:0, val:EVar::IVal(0), pure:true},
+	&FuCoOpDef{name:"atan2",fn_eval:eval_atan2_deg,params:Some(2), prio:0, val:EVar::IVal(0), pure:true},
 	];
 	
 // constant definition array
-const CODEF:[&'static FuCoOpDef;20]=[
-	&FuCoOpDef{name:"pi",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::PI)},
-	&FuCoOpDef{name:"π",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::PI)},
+const CODEF:[&'static FuCoOpDef;21]=[
+	// missing value
+	&FuCoOpDef{name:"null", fn_eval:eval_null, params:None, prio:0, val:EVar::Null, pure:true},
+	&FuCoOpDef{name:"pi",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::PI), pure:true},
+	&FuCoOpDef{name:"π",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::PI), pure:true},
 	// Euler's number
-	&FuCoOpDef{name:"e",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::E)},
+	&FuCoOpDef{name:"e",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(std::f64::consts::E), pure:true},
 	// Golden ratio
-	&FuCoOpDef{name:"phi",  fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.618_033_988_749_894_848_204_586)},
-	&FuCoOpDef{name:"Φ",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.618_033_988_749_894_848_204_586)},
+	&FuCoOpDef{name:"phi",  fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.618_033_988_749_894_848_204_586), pure:true},
+	&FuCoOpDef{name:"Φ",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.618_033_988_749_894_848_204_586), pure:true},
 	// plastic number (nombre radiant), rho
-	&FuCoOpDef{name:"rho",  fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.324_717_957_244_746_025_960_908)},
-	&FuCoOpDef{name:"ρ",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.324_717_957_244_746_025_960_908)},
+	&FuCoOpDef{name:"rho",  fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.324_717_957_244_746_025_960_908), pure:true},
+	&FuCoOpDef{name:"ρ",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.324_717_957_244_746_025_960_908), pure:true},
 	// reference: https://physics.nist.gov/cuu/Constants/index.html
 	// speed of light
-	&FuCoOpDef{name:"c",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(299_792_458.0)},
+	&FuCoOpDef{name:"c",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(299_792_458.0), pure:true},
 	// gravitational constant
-	&FuCoOpDef{name:"G",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.674_30E-11)},
+	&FuCoOpDef{name:"G",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.674_30E-11), pure:true},
 	// Planck constant
-	&FuCoOpDef{name:"h",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.626_070_15e-34)},
+	&FuCoOpDef{name:"h",    fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.626_070_15e-34), pure:true},
 	// Planck mass
-	&FuCoOpDef{name:"pm",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(2.176_434e-8)},
+	&FuCoOpDef{name:"pm",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(2.176_434e-8), pure:true},
 	// Planck time
-	&FuCoOpDef{name:"pt",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(5.391_247e-44)},
+	&FuCoOpDef{name:"pt",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(5.391_247e-44), pure:true},
 	// Planck length
-	&FuCoOpDef{name:"pl",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.616_255e-35)},
+	&FuCoOpDef{name:"pl",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.616_255e-35), pure:true},
 	// elementary charge
-	&FuCoOpDef{name:"qe",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.602_176_634e-19)},
+	&FuCoOpDef{name:"qe",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.602_176_634e-19), pure:true},
 	// electron rest mass
-	&FuCoOpDef{name:"me",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(9.109_383_7015e-31 )},
+	&FuCoOpDef{name:"me",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(9.109_383_7015e-31 ), pure:true},
 	// proton rest mass
-	&FuCoOpDef{name:"mp",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.672_621_923_69e-27)},
+	&FuCoOpDef{name:"mp",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.672_621_923_69e-27), pure:true},
 	// neutron rest mass
-	&FuCoOpDef{name:"mn",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.674_927_498_04e-27)},
+	&FuCoOpDef{name:"mn",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(1.674_927_498_04e-27), pure:true},
 	// Avogadro's number
-	&FuCoOpDef{name:"NA",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.022_140_76e23)},
-	&FuCoOpDef{name:"true", fn_eval:eval_null, params:None, prio:0, val:EVar::BVal(true)},
-	&FuCoOpDef{name:"false",fn_eval:eval_null, params:None, prio:0, val:EVar::BVal(false)},
+	&FuCoOpDef{name:"NA",   fn_eval:eval_null, params:None, prio:0, val:EVar::FVal(6.022_140_76e23), pure:true},
+	&FuCoOpDef{name:"true", fn_eval:eval_null, params:None, prio:0, val:EVar::BVal(true), pure:true},
+	&FuCoOpDef{name:"false",fn_eval:eval_null, params:None, prio:0, val:EVar::BVal(false), pure:true},
 	];
 
 fn is_operator(c:char) -> bool {
 	OP_CHARS.contains(c)
 }
+fn is_ternary(c:char) -> bool {
+	c == CHAR_QMARK || c == CHAR_COLON
+}
 fn is_hexa_char(c:char) -> bool {
 	HEXA_CHARS.contains(c)
 }
@@ -716,45 +1349,76 @@ fn is_exponent_char(c:char) -> bool {
 fn is_hexa_prefix(c:char) -> bool {
 	c=='x' || c=='X'
 }
+// names of functions, constants and variables start with a letter, an underscore or a dollar 
+// (e.g. the previous results $1, $2... of the rpn binary)
+fn is_name_start(c:char) -> bool {
+	c.is_alphabetic() || c==CHAR_UNDERSCORE || c==CHAR_DOLLAR
+}
+fn is_name_char(c:char) -> bool {
+	c.is_alphabetic() || c.is_numeric() || c==CHAR_UNDERSCORE
+}
+// valid name of a function, a constant or a variable
+pub fn is_name(name:&str) -> bool {
+	let mut chars=name.chars();
+	chars.next().is_some_and(is_name_start) && chars.all(is_name_char)
+}
+// name of a function or of a constant, built-in or of the context, which can not be a variable
+// (same tables as get_token_fuco)
+pub fn is_reserved(name:&str, context:Option<&EvalContext>) -> bool {
+	TRIG_DEG.iter().chain(FUDEF.iter()).chain(CODEF.iter()).any(|elt| elt.name == name) ||
+		context.is_some_and(|c| c.find_fn(name).is_some() || c.user_fns.iter().any(|elt| elt.name == name) || c.find_const(name).is_some())
+}
 
 fn lex_error (code:LexErr) -> Result<Option<Token>, RpnError> {
 	Err(RpnError::AnaLex(code))		
 }
 
 impl <'a> Expression <'a> {
-	fn get_token_fuco(& self, c:char, token: &mut Token) -> Result<Option<Token>, RpnError> {
+	fn get_token_fuco(& self, c:char, opar:bool, token: &mut Token) -> Result<Option<Token>, RpnError> {
 		token.svalue.pop();
-		if c == CHAR_SEP || c == CHAR_CPAR || c == CHAR_OPAR || c == CHAR_SP || is_operator(c) {
-			if let Some(fu) = FUDEF.iter().find(|&elt| elt.name == token.svalue) {
+		if c == CHAR_SEP || c == CHAR_CPAR || c == CHAR_OPAR || c == CHAR_SP || is_operator(c) || is_ternary(c) {
+			let degrees=self.context.is_some_and(|c| c.degrees);
+			// the functions registered by the host take precedence over the built-in ones
+			if let Some(idx) = self.context.and_then(|c| c.find_fn(&token.svalue)) {
+				token.kind=TokKind::Function;
+				token.ufn = Some(idx);
+				return Ok(Some(token.clone()));
+			}
+			else if let Some(fu) = TRIG_DEG.iter().filter(|_| degrees).chain(FUDEF.iter()).find(|&elt| elt.name == token.svalue) {
 				token.kind=TokKind::Function;
 				token.refdef = fu;
 				return Ok(Some(token.clone()));
 			}
+			// as for the functions, the constants registered by the host hide the built-in ones (e.g. 'h' or 'e')
+			else if let Some(val) = self.context.and_then(|c| c.find_const(&token.svalue)) {
+				token.kind=TokKind::Constant;
+				token.val=val.clone();
+				return Ok(Some(token.clone()));
+			}
 			else if let Some(co) = CODEF.iter().find(|&elt| elt.name == token.svalue) {
 				token.kind=TokKind::Constant;
 				token.val=co.val.clone();
 				token.refdef = co;
 				return Ok(Some(token.clone()));
 			}
-			else if let Some(context)=self.context {
-				if let Some(fu) = context.user_fns.iter().find(|&elt| elt.name == token.svalue) {
-					token.kind=TokKind::Function;
-					token.refdef = fu;
-					return Ok(Some(token.clone()));
-				}
-				else {
-					return lex_error(LexErr::FunctionOrConstNotFound);
-				}
+			else if let Some(fu) = self.context.and_then(|c| c.user_fns.iter().find(|&elt| elt.name == token.svalue)) {
+				token.kind=TokKind::Function;
+				token.refdef = fu;
+				return Ok(Some(token.clone()));
 			}
-			else {
+			if opar {
+				// an unknown name followed by a parenthesis can only be a function
 				return lex_error(LexErr::FunctionOrConstNotFound);
 			}
+			// any other unknown name is a variable, its value is supplied at evaluation time
+			token.kind=TokKind::Variable;
+			return Ok(Some(token.clone()));
 		}
 		return lex_error(LexErr::FunctionOrConst)
 	}
 
 	fn get_token_number(& self, c:char, numtype:NumType, errcode:LexErr, token: &mut Token) -> Result<Option<Token>, RpnError> {
-		if is_operator(c) || c == CHAR_CPAR || c == CHAR_SEP || c == CHAR_SP {
+		if is_operator(c) || c == CHAR_CPAR || c == CHAR_SEP || c == CHAR_SP || is_ternary(c) {
 			token.svalue.pop();
 			token.kind = TokKind::Number;
 			match numtype {
@@ -801,6 +1465,10 @@ impl <'a> Expression <'a> {
 		let mut cpy = sce.clone();
 		let mut token = Token::new();
 		let mut step=Lex::Start;
+		// for strings: delimiter, value after the escape sequences, and code point of an escape \u{XXXX}
+		let mut quote=CHAR_QUOTE;
+		let mut sval=String::new();
+		let mut uni=String::new();
 		//while let Some(cc) = cpy.next()  {
 		loop {
 			let cc = cpy.next();
@@ -823,7 +1491,7 @@ impl <'a> Expression <'a> {
 					sce.next();
 					continue;
 				}
-				else if step != Lex::CString {
+				else if !step.is_string() {
 					c=' ';
 				}
 			}
@@ -838,13 +1506,14 @@ impl <'a> Expression <'a> {
 				else if c == CHAR_DECPOINT { //number, after dec point
 					step=Lex::NumSigAdp;
 				}
-				else if c == CHAR_QUOTE { // string
+				else if c == CHAR_QUOTE || c == CHAR_APOS { // string
 					step=Lex::CString;
+					quote=c;
 				}
 				else if is_operator(c) { // operator
 					step=Lex::Operator;
 				}
-				else if c.is_alphabetic() { // function or constant				
+				else if is_name_start(c) { // function, constant or variable
 					step=Lex::FuncConst;
 				}
 				else if (c == CHAR_OPAR) || (c == CHAR_CPAR) { //opening/closing parenthesis
@@ -852,6 +1521,10 @@ impl <'a> Expression <'a> {
 					sce.next();
 					return Ok(Some(token));
 				}
+				else if is_ternary(c) { // '?' or ':' of the ternary operator, always single character
+					sce.next();
+					return self.get_token_operator(LexErr::BadOperatorShort, &mut token);
+				}
 				else if c == CHAR_SEP { //separator
 					token.kind = TokKind::Separator;
 					sce.next();
@@ -940,7 +1613,7 @@ impl <'a> Expression <'a> {
 					sce.next();
 					return self.get_token_operator(LexErr::BadOperatorLong, &mut token);
 				}
-				else if c == CHAR_QUOTE {
+				else if c == CHAR_QUOTE || c == CHAR_APOS {
 					return lex_error(LexErr::QuoteAfterOp);
 				}
 				else {
@@ -948,23 +1621,70 @@ impl <'a> Expression <'a> {
 					return self.get_token_operator(LexErr::BadOperatorShort, &mut token);
 				}
 			}
+			else if step.is_string() && last {
+				// end of the expression reached before the closing quote
+				return lex_error(LexErr::UnterminatedString);
+			}
 			else if step == Lex::CString {
-				if c == CHAR_QUOTE {
+				if c == quote {
 					token.kind = TokKind::CString;
-					// copy the string without the double quote at the begining and at the end
-					// works even for an utf-8 string as the quote character is single byte 
-					// NB: len() gives the string's number of bytes
-					token.val=EVar::SVal(String::from(&token.svalue[1..token.svalue.len() - 1]));
+					// the value is the string without its quotes, and with its escape sequences replaced
+					token.val=EVar::SVal(sval);
 					sce.next();
 					return Ok(Some(token));
 				}
+				else if c == CHAR_ESC {
+					step=Lex::CStringEsc;
+				}
+				else {
+					sval.push(c);
+				}
+			}
+			else if step == Lex::CStringEsc {
+				step=Lex::CString;
+				match c {
+					'n' => sval.push('\n'),
+					't' => sval.push('\t'),
+					'r' => sval.push('\r'),
+					'0' => sval.push('\0'),
+					CHAR_QUOTE | CHAR_APOS | CHAR_ESC => sval.push(c),
+					'u' => step=Lex::CStringUniOpen,
+					_ => return lex_error(LexErr::BadEscape),
+				}
+			}
+			else if step == Lex::CStringUniOpen {
+				if c == '{' {
+					step=Lex::CStringUni;
+					uni.clear();
+				}
+				else {
+					return lex_error(LexErr::BadEscape);
+				}
+			}
+			else if step == Lex::CStringUni {
+				if c == '}' {
+					match u32::from_str_radix(&uni, 16).ok().and_then(char::from_u32) {
+						Some(uc) => sval.push(uc),
+						None => return lex_error(LexErr::BadEscape),
+					}
+					step=Lex::CString;
+				}
+				else if is_hexa_char(c) && uni.len() < 6 {
+					uni.push(c);
+				}
+				else {
+					return lex_error(LexErr::BadEscape);
+				}
 			}
 			else if step == Lex::FuncConst {
-				if c.is_alphabetic() || c.is_numeric() {
+				if is_name_char(c) {
 					step = Lex::FuncConst;
 				}
 				else {
-					return self.get_token_fuco(c, &mut token);
+					// a parenthesis after the name, possibly after spaces, makes it a function call
+					let opar=c == CHAR_OPAR || (c == CHAR_SP &&
+						cpy.as_str().trim_start_matches(|c:char| c.is_control() || c.is_whitespace()).starts_with(CHAR_OPAR));
+					return self.get_token_fuco(c, opar, &mut token);
 				}
 			}
 			sce.next();
@@ -0,0 +1,293 @@
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::collections::HashMap;
+use std::fs::File;
+use std::process::ExitCode;
+use rpn::{EvalContext, Expression, ExprError, RpnError, is_name, is_reserved};
+use rpn::srlvariant::EVar;
+use rpn::stack::StackCalc;
+
+// rpn binary: interactive calculator, each line being an infix expression evaluated exactly as
+// by the library; the results are kept as 'ans' (last result) and $1, $2... (in order)
+// commands:
+// :tokens <expr>	tokens of the expression (display_tokenized)
+// :rpn <expr>		parse stack of the expression (display_parsed)
+// :history			previous results
+// :stack			stack mode: the lines are postfix input for a StackCalc, whose stack is shown after each line
+// :infix			back to the infix expressions
+// :quit			end, as the end of the input
+//
+// rpn csv [--file FILE] NAME=EXPR...: batch evaluation of a CSV file (or of the standard input)
+// see fn csv
+
+const PROMPT:&str="> ";
+const PROMPT_STACK:&str="stack> ";
+
+struct Repl {
+	context:EvalContext,
+	history:Vec<(String, EVar)>,
+	// stack calculator of the stack mode
+	calc:Option<StackCalc>,
+	// the input is a terminal: prompt, and position of the errors shown under the input
+	interactive:bool,
+}
+
+impl Repl {
+	fn new(interactive:bool) -> Repl {
+		Repl{context:EvalContext::new(), history:vec![], calc:None, interactive}
+	}
+	fn prompt(&self) -> &'static str {
+		if self.calc.is_some() {PROMPT_STACK} else {PROMPT}
+	}
+	// processes a line of input, returns the text to print, None to quit
+	fn line(&mut self, input:&str) -> Option<String> {
+		let line=input.trim();
+		let (cmd, arg)=match line.split_once(char::is_whitespace) {
+			Some((cmd, arg)) => (cmd, arg.trim()),
+			None => (line, ""),
+		};
+		// position of the argument of a command in the input, for the errors
+		let after=&line[cmd.len()..];
+		let offset=input.chars().count()-input.trim_start().chars().count()+cmd.chars().count()
+			+after.chars().count()-after.trim_start().chars().count();
+		match cmd {
+			"" => Some(String::new()),
+			":quit" | ":q" => None,
+			":tokens" => {
+				let expr=Expression::new(arg, Some(&self.context));
+				match expr.parse_error() {
+					// only the errors of the analysis prevent the tokens from being shown
+					Some(e) if matches!(e.error, RpnError::AnaLex(_)) => Some(self.error(&e, offset)),
+					_ => Some(expr.display_tokenized()),
+				}
+			},
+			":rpn" => {
+				let expr=Expression::new(arg, Some(&self.context));
+				match expr.parse_error() {
+					Some(e) => Some(self.error(&e, offset)),
+					None => Some(expr.display_parsed()),
+				}
+			},
+			":history" => Some(self.history.iter().enumerate()
+				.map(|(i, (exp, val))| format!("${} = {}\t{}", i+1, val, exp))
+				.collect::<Vec<String>>().join("\n")),
+			":stack" => {
+				self.calc.get_or_insert_with(StackCalc::new);
+				Some(String::new())
+			},
+			":infix" => {
+				self.calc=None;
+				Some(String::new())
+			},
+			_ if cmd.starts_with(':') => Some(format!("unknown command {}", cmd)),
+			// leading spaces kept for the position of the errors
+			_ if self.calc.is_some() => Some(self.eval_stack(input.trim_end())),
+			_ => Some(self.eval(input.trim_end())),
+		}
+	}
+	fn eval(&mut self, exp:&str) -> String {
+		let mut expr=Expression::new(exp, Some(&self.context));
+		match expr.eval() {
+			Ok(Some(val)) => {
+				let msg=format!("${} = {}", self.history.len()+1, expr.result());
+				self.history.push((exp.trim().to_string(), val.clone()));
+				self.context.register_const(&format!("${}", self.history.len()), val.clone());
+				self.context.register_const("ans", val);
+				msg
+			},
+			Ok(None) => String::new(),
+			Err(e) => self.error(&e, 0),
+		}
+	}
+	// postfix input, the values of the stack are shown from the bottom to the top
+	fn eval_stack(&mut self, input:&str) -> String {
+		let calc=self.calc.as_mut().unwrap();
+		match calc.eval(input, Some(&self.context)) {
+			Ok(_) if calc.stack().is_empty() => "(empty)".to_string(),
+			Ok(_) => calc.stack().iter().map(|v| v.to_string()).collect::<Vec<String>>().join(" "),
+			Err(e) => self.error(&e, 0),
+		}
+	}
+	// error of an expression starting at the character 'offset' of the input line
+	fn error(&self, e:&ExprError, offset:usize) -> String {
+		match e.column() {
+			// the input line is right after the prompt
+			Some(col) if self.interactive => format!("{}^\nerror: {}", " ".repeat(self.prompt().len()+offset+col-1), e),
+			_ => format!("error: {}", e),
+		}
+	}
+}
+
+// csv subcommand: each column of the CSV is a variable named by its header, and each definition
+// NAME=EXPR adds a column NAME computed from the row (including the columns of the previous definitions)
+// - the fields are integers, floats, booleans (true or false) or strings; an empty field is null
+// - the fields can be quoted with '"' (a quote being doubled inside), but not span several lines
+// - a row whose evaluation fails has an empty field, the error being reported on the error output
+// - a row which has not the number of fields of the header is reported too, and completed with empty
+// fields or truncated
+fn csv(args:&[String]) -> Result<bool, String> {
+	let mut file=None;
+	let mut defs:Vec<(&str, &str)>=vec![];
+	let mut iarg=args.iter();
+	while let Some(arg)=iarg.next() {
+		if arg == "--file" || arg == "-f" {
+			file=Some(iarg.next().ok_or("missing file name after --file")?);
+		}
+		else {
+			defs.push(arg.split_once('=').ok_or(format!("definition NAME=EXPR expected instead of '{}'", arg))?);
+		}
+	}
+	let input:Box<dyn BufRead>=match file {
+		Some(name) => Box::new(BufReader::new(File::open(name).map_err(|e| format!("{}: {}", name, e))?)),
+		None => Box::new(io::stdin().lock()),
+	};
+	let context=EvalContext::new();
+	let mut exprs=vec![];
+	for (name, exp) in &defs {
+		let name=name.trim();
+		if !is_name(name) {
+			return Err(format!("invalid name '{}'", name));
+		}
+		if is_reserved(name, Some(&context)) {
+			return Err(format!("'{}' is the name of a constant or a function", name));
+		}
+		if exprs.iter().any(|(n, _)| n == name) {
+			return Err(format!("'{}' already defined", name));
+		}
+		let expr=Expression::new(exp, Some(&context));
+		if !expr.is_parsed() {
+			// the result is the error of the parsing
+			return Err(format!("{}: {}", name, expr.result()));
+		}
+		exprs.push((name.to_string(), expr));
+	}
+	let mut lines=input.lines();
+	// the names of the columns are trimmed, as the fields
+	let header:Vec<String>=match lines.next() {
+		Some(line) => csv_split(&line.map_err(|e| e.to_string())?).iter().map(|name| name.trim().to_string()).collect(),
+		None => return Ok(true),
+	};
+	// a column named as a constant or a function could not be read as a variable
+	if let Some(name)=header.iter().find(|name| is_reserved(name, Some(&context))) {
+		return Err(format!("column '{}' is the name of a constant or a function", name));
+	}
+	if let Some((name, _))=exprs.iter().find(|(name, _)| header.contains(name)) {
+		return Err(format!("'{}' already defined as a column", name));
+	}
+	let mut out=io::stdout().lock();
+	let names:Vec<String>=header.iter().cloned().chain(exprs.iter().map(|e| e.0.clone())).collect();
+	let _=writeln!(out, "{}", names.iter().map(|n| csv_quote(n)).collect::<Vec<String>>().join(","));
+	let mut ok=true;
+	for (iline, line) in lines.enumerate() {
+		let mut fields=csv_split(&line.map_err(|e| e.to_string())?);
+		if fields.len() != header.len() {
+			// reported, and completed with empty fields or truncated so that the columns stay under their names
+			eprintln!("line {}: {} fields instead of {}", iline+2, fields.len(), header.len());
+			ok=false;
+			fields.resize(header.len(), String::new());
+		}
+		let mut vars:HashMap<String, EVar>=header.iter().zip(fields.iter())
+			.map(|(name, field)| (name.clone(), csv_value(field))).collect();
+		let mut row:Vec<String>=fields.iter().map(|f| csv_quote(f)).collect();
+		for (name, expr) in exprs.iter_mut() {
+			let val=match expr.eval_with(&vars) {
+				Ok(val) => val.unwrap_or(EVar::Null),
+				Err(e) => {
+					// the header is the line 1
+					eprintln!("line {}: {}: {}", iline+2, name, e);
+					ok=false;
+					EVar::Null
+				},
+			};
+			row.push(match val {
+				EVar::Null => String::new(),
+				_ => csv_quote(&val.to_string()),
+			});
+			vars.insert(name.clone(), val);
+		}
+		let _=writeln!(out, "{}", row.join(","));
+	}
+	Ok(ok)
+}
+
+fn csv_split(line:&str) -> Vec<String> {
+	let mut fields=vec![];
+	let mut field=String::new();
+	let mut quoted=false;
+	let mut chars=line.chars().peekable();
+	while let Some(c)=chars.next() {
+		match c {
+			'"' if quoted && chars.peek() == Some(&'"') => {
+				field.push('"');
+				chars.next();
+			},
+			'"' => quoted = !quoted,
+			',' if !quoted => fields.push(std::mem::take(&mut field)),
+			_ => field.push(c),
+		}
+	}
+	fields.push(field);
+	fields
+}
+
+fn csv_quote(field:&str) -> String {
+	if field.contains(['"', ',']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	}
+	else {
+		field.to_string()
+	}
+}
+
+fn csv_value(field:&str) -> EVar {
+	let field=field.trim();
+	if field.is_empty() {
+		EVar::Null
+	}
+	else if let Ok(i)=field.parse::<i64>() {
+		EVar::IVal(i)
+	}
+	else if let Ok(f)=field.parse::<f64>() {
+		EVar::FVal(f)
+	}
+	else if let Ok(b)=field.parse::<bool>() {
+		EVar::BVal(b)
+	}
+	else {
+		EVar::SVal(field.to_string())
+	}
+}
+
+fn main() -> ExitCode {
+	let args:Vec<String>=std::env::args().collect();
+	if args.get(1).is_some_and(|a| a == "csv") {
+		return match csv(&args[2..]) {
+			Ok(true) => ExitCode::SUCCESS,
+			Ok(false) => ExitCode::FAILURE,
+			Err(msg) => {
+				eprintln!("rpn csv: {}", msg);
+				ExitCode::from(2)
+			},
+		};
+	}
+	let stdin=io::stdin();
+	let mut repl=Repl::new(stdin.is_terminal());
+	let mut input=stdin.lock();
+	let mut line=String::new();
+	loop {
+		if repl.interactive {
+			print!("{}", repl.prompt());
+			let _=io::stdout().flush();
+		}
+		line.clear();
+		match input.read_line(&mut line) {
+			Ok(0) | Err(_) => break,
+			Ok(_) => {},
+		}
+		match repl.line(&line) {
+			Some(msg) if msg.is_empty() => {},
+			Some(msg) => println!("{}", msg),
+			None => break,
+		}
+	}
+	ExitCode::SUCCESS
+}
@@ -1,5 +1,6 @@
 use std::ops::*;
 use std::cmp::*;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum EVar {
@@ -7,6 +8,9 @@ pub enum EVar {
 	IVal(i64),
 	FVal(f64),
 	BVal(bool),
+	// missing value (e.g. unavailable input); it propagates through the arithmetic operations 
+	// and the numeric functions, and it is false as a condition
+	Null,
 }
 
 impl EVar {
@@ -16,8 +20,32 @@ impl EVar {
 			EVar::BVal(_) => false,
 			EVar::FVal(_) => true,
 			EVar::SVal(_) => false,
+			EVar::Null => false,
 		}		
 	}
+	pub fn is_null(&self) -> bool {
+		match *self {
+			EVar::Null => true,
+			_ => false,
+		}
+	}
+	fn is_string(&self) -> bool {
+		match *self {
+			EVar::SVal(_) => true,
+			_ => false,
+		}
+	}
+	// both values are strings, for the lexicographic comparisons
+	fn str_pair<'a>(&'a self, other:&'a EVar) -> Option<(&'a str, &'a str)> {
+		match (self, other) {
+			(EVar::SVal(s1), EVar::SVal(s2)) => Some((s1, s2)),
+			_ => None,
+		}
+	}
+	// only one of the values is a string
+	fn str_mixed(&self, other:&EVar) -> bool {
+		matches!(self, EVar::SVal(_)) != matches!(other, EVar::SVal(_))
+	}
 	
 	pub fn to_float(&self) -> f64 {
 		match self {
@@ -25,6 +53,7 @@ impl EVar {
 			EVar::BVal(b1) => return *b1 as i64 as f64,
 			EVar::FVal(f1) => return *f1,
 			EVar::SVal(s1) => return s1.parse().unwrap_or(f64::NAN),
+			EVar::Null => return f64::NAN,
 		}
 	}
 
@@ -34,6 +63,7 @@ impl EVar {
 			EVar::BVal(b1) => return *b1 as i64,
 			EVar::FVal(f1) => return *f1 as i64,
 			EVar::SVal(s1) => return s1.parse().unwrap_or(0),
+			EVar::Null => return 0,
 		}
 	}
 
@@ -43,62 +73,125 @@ impl EVar {
 			EVar::BVal(b1) => return b1,
 			EVar::FVal(f1) => return f1 != 0.0,
 			EVar::SVal(_) => return false,
+			EVar::Null => return false,
 		}
 	}
 	
-	pub fn ev_add(&self, other:& EVar) -> EVar {
+	// arithmetic operation on floats if one of the values is a float, else on integers, 
+	// None if the integer operation overflows (or is a division by 0)
+	fn checked_arith(&self, other:& EVar, iop:fn(i64, i64) -> Option<i64>, fop:fn(f64, f64) -> f64) -> Option<EVar> {
+		if self.is_null() || other.is_null() {
+			return Some(EVar::Null);
+		}
 		if self.is_float() || other.is_float() {
-			return EVar::FVal(self.to_float()+other.to_float());
+			return Some(EVar::FVal(fop(self.to_float(), other.to_float())));
+		}
+		iop(self.to_int(), other.to_int()).map(EVar::IVal)
+	}
+	pub fn checked_add(&self, other:& EVar) -> Option<EVar> {
+		// with a string, the addition is a concatenation
+		if !self.is_null() && !other.is_null() && (self.is_string() || other.is_string()) {
+			return Some(EVar::SVal(format!("{}{}", self, other)));
 		}
-		return EVar::IVal(self.to_int()+other.to_int());
+		self.checked_arith(other, i64::checked_add, |f1, f2| f1+f2)
+	}
+	pub fn checked_sub(&self, other:& EVar) -> Option<EVar> {
+		self.checked_arith(other, i64::checked_sub, |f1, f2| f1-f2)
+	}
+	pub fn checked_mul(&self, other:& EVar) -> Option<EVar> {
+		self.checked_arith(other, i64::checked_mul, |f1, f2| f1*f2)
+	}
+	pub fn checked_div(&self, other:& EVar) -> Option<EVar> {
+		self.checked_arith(other, i64::checked_div, |f1, f2| f1/f2)
+	}
+	pub fn checked_rem(&self, other:& EVar) -> Option<EVar> {
+		self.checked_arith(other, i64::checked_rem, |f1, f2| f1%f2)
+	}
+	// division rounded toward minus infinity
+	pub fn checked_floordiv(&self, other:& EVar) -> Option<EVar> {
+		self.checked_arith(other, |i1, i2| {
+			let q=i1.checked_div(i2)?;
+			Some(if i1%i2 != 0 && ((i1 < 0) != (i2 < 0)) {q-1} else {q})
+		}, |f1, f2| (f1/f2).floor())
+	}
+
+	// the unchecked operations never panic: an integer overflow gives the result as a float
+	pub fn ev_add(&self, other:& EVar) -> EVar {
+		self.checked_add(other).unwrap_or_else(|| EVar::FVal(self.to_float()+other.to_float()))
 	}
 
 	pub fn ev_sub(&self, other:& EVar) -> EVar {
-		if self.is_float() || other.is_float() {
-			return EVar::FVal(self.to_float()-other.to_float());
-		}
-		return EVar::IVal(self.to_int()-other.to_int());
+		self.checked_sub(other).unwrap_or_else(|| EVar::FVal(self.to_float()-other.to_float()))
 	}
 
 	pub fn ev_mul(&self, other:& EVar) -> EVar {
-		if self.is_float() || other.is_float() {
-			return EVar::FVal(self.to_float()*other.to_float());
-		}
-		return EVar::IVal(self.to_int()*other.to_int());
+		self.checked_mul(other).unwrap_or_else(|| EVar::FVal(self.to_float()*other.to_float()))
 	}
 
 	pub fn ev_div(&self, other:& EVar) -> EVar {
-		if self.is_float() || other.is_float() {
-			return EVar::FVal(self.to_float()/other.to_float());
-		}
-		return EVar::IVal(self.to_int()/other.to_int());
+		self.checked_div(other).unwrap_or_else(|| EVar::FVal(self.to_float()/other.to_float()))
 	}
-	// comparison operations (return a boolean)
+	// comparison operations (return a boolean); strings are compared lexicographically
+	// null is only equal to null, and any ordering comparison with null is false
+	// a string is never equal to a value of another type, and not ordered with it (as for ==)
 	pub fn ev_eq(&self, other:& EVar) -> bool {
+		if self.is_null() || other.is_null() {
+			return self.is_null() && other.is_null();
+		}
+		if self.str_mixed(other) {
+			return false;
+		}
+		if let Some((s1, s2))=self.str_pair(other) {
+			return s1 == s2;
+		}
 		if self.is_float() || other.is_float() {
 			return self.to_float() == other.to_float();
 		}
 		return self.to_int()==other.to_int();
 	}
 	pub fn ev_infeq(&self, other:& EVar) -> bool {
+		if self.is_null() || other.is_null() || self.str_mixed(other) {
+			return false;
+		}
+		if let Some((s1, s2))=self.str_pair(other) {
+			return s1 <= s2;
+		}
 		if self.is_float() || other.is_float() {
 			return self.to_float() <= other.to_float();
 		}
 		return self.to_int()<=other.to_int();
 	}
 	pub fn ev_inf(&self, other:& EVar) -> bool {
+		if self.is_null() || other.is_null() || self.str_mixed(other) {
+			return false;
+		}
+		if let Some((s1, s2))=self.str_pair(other) {
+			return s1 < s2;
+		}
 		if self.is_float() || other.is_float() {
 			return self.to_float() < other.to_float();
 		}
 		return self.to_int()<other.to_int();
 	}
 	pub fn ev_supeq(&self, other:& EVar) -> bool {
+		if self.is_null() || other.is_null() || self.str_mixed(other) {
+			return false;
+		}
+		if let Some((s1, s2))=self.str_pair(other) {
+			return s1 >= s2;
+		}
 		if self.is_float() || other.is_float() {
 			return self.to_float() >= other.to_float();
 		}
 		return self.to_int()>=other.to_int();
 	}
 	pub fn ev_sup(&self, other:& EVar) -> bool {
+		if self.is_null() || other.is_null() || self.str_mixed(other) {
+			return false;
+		}
+		if let Some((s1, s2))=self.str_pair(other) {
+			return s1 > s2;
+		}
 		if self.is_float() || other.is_float() {
 			return self.to_float() > other.to_float();
 		}
@@ -112,6 +205,7 @@ impl EVar {
 			EVar::BVal(b1) => !b1,
 			EVar::FVal(f1) => f1 == 0.0,
 			EVar::SVal(_) => false,
+			EVar::Null => false,
 		}
 	}
 	
@@ -122,6 +216,7 @@ impl EVar {
 			EVar::BVal(b1) => return !(b1 as i64),
 			EVar::FVal(f1) => return !(f1 as i64),
 			EVar::SVal(_) => return 0,
+			EVar::Null => return 0,
 		}
 	}
 	pub fn ev_band(&self, other:& EVar) -> i64 {
@@ -133,11 +228,19 @@ impl EVar {
 	pub fn ev_bitxor(&self, other:& EVar) -> i64 {
 		self.to_int() ^ other.to_int()
 	}
+	// shifts; None if the shift is negative or not less than 64 bits
+	pub fn checked_shl(&self, other:& EVar) -> Option<i64> {
+		u32::try_from(other.to_int()).ok().and_then(|n| self.to_int().checked_shl(n))
+	}
+	pub fn checked_shr(&self, other:& EVar) -> Option<i64> {
+		u32::try_from(other.to_int()).ok().and_then(|n| self.to_int().checked_shr(n))
+	}
+	// all the bits are shifted out by a shift out of range
 	pub fn ev_shl(&self, other:& EVar) -> i64 {
-		self.to_int()<<other.to_int()
+		self.checked_shl(other).unwrap_or(0)
 	}
 	pub fn ev_shr(&self, other:& EVar) -> i64 {
-		self.to_int()>>other.to_int()
+		self.checked_shr(other).unwrap_or(if self.to_int() < 0 {-1} else {0})
 	}
 	
 }
@@ -151,7 +254,7 @@ impl PartialEq for EVar {
 					EVar::IVal(i1) => i1 == i2,
 					EVar::BVal(b1) => b1 == (i2 != 0),
 					EVar::FVal(f1) => f1 == i2 as f64,
-					EVar::SVal(_) => false,
+					EVar::SVal(_) | EVar::Null => false,
 				}
 			}
 			EVar::BVal(b2) => {
@@ -159,7 +262,7 @@ impl PartialEq for EVar {
 					EVar::IVal(i1) => (i1!=0) == b2,
 					EVar::BVal(b1) => b1 == b2,
 					EVar::FVal(f1) => b2 == (f1!=0.0),
-					EVar::SVal(_) => false,
+					EVar::SVal(_) | EVar::Null => false,
 				}
 			}
 			EVar::FVal(f2) => {
@@ -167,10 +270,28 @@ impl PartialEq for EVar {
 					EVar::IVal(i1) =>  i1 as f64 == f2,
 					EVar::BVal(b1) => b1 == (f2!=0.0),
 					EVar::FVal(f1) => f1 == f2,
-					EVar::SVal(_) => false,
+					EVar::SVal(_) | EVar::Null => false,
 				}
 			},
-			EVar::SVal(_) => return false,
+			EVar::SVal(ref s2) => {
+				match *self {
+					EVar::SVal(ref s1) => s1 == s2,
+					_ => false,
+				}
+			},
+			EVar::Null => self.is_null(),
+		}
+	}
+}
+
+impl fmt::Display for EVar {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			EVar::BVal(b) => write!(f, "{}", b),
+			EVar::FVal(fl) => write!(f, "{}", fl),
+			EVar::IVal(i) => write!(f, "{}", i),
+			EVar::SVal(s) => write!(f, "{}", s),
+			EVar::Null => write!(f, "null"),
 		}
 	}
 }
@@ -208,47 +329,112 @@ impl Div for EVar {
 }
 
 impl EVar {
+	// numeric function with a float result, null if the value is null
+	fn float_fn(&self, f:fn(f64) -> f64) -> EVar {
+		if self.is_null() {
+			return EVar::Null;
+		}
+		EVar::FVal(f(self.to_float()))
+	}
 	pub fn sin(&self) -> EVar {
-		EVar::FVal(self.to_float().sin())
+		self.float_fn(f64::sin)
 	}
 	pub fn cos(&self) -> EVar {
-		EVar::FVal(self.to_float().cos())
+		self.float_fn(f64::cos)
 	}
 	pub fn tan(&self) -> EVar {
-		EVar::FVal(self.to_float().tan())
+		self.float_fn(f64::tan)
+	}
+	pub fn asin(&self) -> EVar {
+		self.float_fn(f64::asin)
+	}
+	pub fn acos(&self) -> EVar {
+		self.float_fn(f64::acos)
+	}
+	pub fn atan(&self) -> EVar {
+		self.float_fn(f64::atan)
+	}
+	// angle of the point (x, y), 'self' being y
+	pub fn atan2(&self, x:& EVar) -> EVar {
+		if self.is_null() || x.is_null() {
+			return EVar::Null;
+		}
+		EVar::FVal(self.to_float().atan2(x.to_float()))
+	}
+	pub fn sinh(&self) -> EVar {
+		self.float_fn(f64::sinh)
+	}
+	pub fn cosh(&self) -> EVar {
+		self.float_fn(f64::cosh)
+	}
+	pub fn tanh(&self) -> EVar {
+		self.float_fn(f64::tanh)
+	}
+	pub fn asinh(&self) -> EVar {
+		self.float_fn(f64::asinh)
+	}
+	pub fn acosh(&self) -> EVar {
+		self.float_fn(f64::acosh)
+	}
+	pub fn atanh(&self) -> EVar {
+		self.float_fn(f64::atanh)
+	}
+	// conversion of an angle in radians to degrees, and of an angle in degrees to radians
+	pub fn deg(&self) -> EVar {
+		self.float_fn(f64::to_degrees)
+	}
+	pub fn rad(&self) -> EVar {
+		self.float_fn(f64::to_radians)
 	}
 	pub fn exp(&self) -> EVar {
-		EVar::FVal(self.to_float().exp())
+		self.float_fn(f64::exp)
 	}
 	pub fn ln(&self) -> EVar {
-		EVar::FVal(self.to_float().ln())
+		self.float_fn(f64::ln)
 	}
 	pub fn log10(&self) -> EVar {
-		EVar::FVal(self.to_float().log10())
+		self.float_fn(f64::log10)
 	}
 	pub fn sqrt(&self) -> EVar {
-		EVar::FVal(self.to_float().sqrt())
+		self.float_fn(f64::sqrt)
 	}
 	pub fn cbrt(&self) -> EVar {
-		EVar::FVal(self.to_float().cbrt())
+		self.float_fn(f64::cbrt)
 	}
-	pub fn pow(&self, exp:& EVar) -> EVar {
+	// None if the integer power overflows
+	pub fn checked_pow(&self, exp:& EVar) -> Option<EVar> {
+		if self.is_null() || exp.is_null() {
+			return Some(EVar::Null);
+		}
 		if exp.is_float() {
-			return EVar::FVal(self.to_float().powf(exp.to_float()));
+			return Some(EVar::FVal(self.to_float().powf(exp.to_float())));
 		}
 		else {
 			let iexp=exp.to_int();
 			if iexp==0 {
-				return EVar::IVal(1);
+				return Some(EVar::IVal(1));
 			}
 			else if iexp<0 || self.is_float() {
-				return EVar::FVal(self.to_float().powi(iexp as i32));
+				return Some(EVar::FVal(self.to_float().powf(iexp as f64)));
 			}
-			return EVar::IVal(self.to_int().pow(iexp as u32));
+			// exact results whatever the exponent, even beyond u32
+			return match self.to_int() {
+				0 => Some(EVar::IVal(0)),
+				1 => Some(EVar::IVal(1)),
+				-1 => Some(EVar::IVal(if iexp % 2 == 0 {1} else {-1})),
+				base => u32::try_from(iexp).ok().and_then(|e| base.checked_pow(e)).map(EVar::IVal),
+			};
 		}
 	}
+	pub fn pow(&self, exp:& EVar) -> EVar {
+		self.checked_pow(exp).unwrap_or_else(|| EVar::FVal(self.to_float().powf(exp.to_float())))
+	}
 
+	// null values are ignored by max and min
 	pub fn max(&self, comp:& EVar) -> EVar {
+		if self.is_null() || comp.is_null() {
+			return if self.is_null() {comp.clone()} else {self.clone()};
+		}
 		if self.is_float() || comp.is_float() {
 			let fself=self.to_float();
 			let fcomp=comp.to_float();
@@ -262,6 +448,9 @@ impl EVar {
 	}
 
 	pub fn min(&self, comp:& EVar) -> EVar {
+		if self.is_null() || comp.is_null() {
+			return if self.is_null() {comp.clone()} else {self.clone()};
+		}
 		if self.is_float() || comp.is_float() {
 			let fself=self.to_float();
 			let fcomp=comp.to_float();
@@ -273,4 +462,93 @@ impl EVar {
 			return EVar::IVal(if icomp<iself {icomp} else {iself});
 		}
 	}
+
+	// rounding functions: an integer is unchanged, a float is rounded to a float
+	fn round_fn(&self, f:fn(f64) -> f64) -> EVar {
+		match *self {
+			EVar::FVal(f1) => EVar::FVal(f(f1)),
+			EVar::Null => EVar::Null,
+			_ => EVar::IVal(self.to_int()),
+		}
+	}
+	pub fn floor(&self) -> EVar {
+		self.round_fn(f64::floor)
+	}
+	pub fn ceil(&self) -> EVar {
+		self.round_fn(f64::ceil)
+	}
+	pub fn trunc(&self) -> EVar {
+		self.round_fn(f64::trunc)
+	}
+	// fractional part, of the sign of the value
+	pub fn frac(&self) -> EVar {
+		match *self {
+			EVar::FVal(f1) => EVar::FVal(f1.fract()),
+			EVar::Null => EVar::Null,
+			_ => EVar::IVal(0),
+		}
+	}
+	// rounding to 'digits' decimals (to tens, hundreds... if negative), half away from zero
+	// None if the rounded integer overflows
+	pub fn checked_round(&self, digits:& EVar) -> Option<EVar> {
+		if self.is_null() || digits.is_null() {
+			return Some(EVar::Null);
+		}
+		let digits=digits.to_int().clamp(-308, 308) as i32;
+		match *self {
+			EVar::FVal(f1) => {
+				let scale=10f64.powi(digits);
+				let rounded=(f1*scale).round()/scale;
+				Some(EVar::FVal(if rounded.is_finite() {rounded} else {f1}))
+			},
+			_ => {
+				let i1=self.to_int();
+				if digits >= 0 {
+					return Some(EVar::IVal(i1));
+				}
+				let scale=match 10i64.checked_pow(digits.unsigned_abs()) {
+					Some(scale) => scale,
+					None => return Some(EVar::IVal(0)),
+				};
+				let rem=i1%scale;
+				if rem.unsigned_abs()*2 >= scale.unsigned_abs() {
+					(i1-rem).checked_add(scale*i1.signum()).map(EVar::IVal)
+				}
+				else {
+					Some(EVar::IVal(i1-rem))
+				}
+			},
+		}
+	}
+	// None for the absolute value of the minimum integer
+	pub fn checked_abs(&self) -> Option<EVar> {
+		match *self {
+			EVar::FVal(f1) => Some(EVar::FVal(f1.abs())),
+			EVar::Null => Some(EVar::Null),
+			_ => self.to_int().checked_abs().map(EVar::IVal),
+		}
+	}
+	// -1, 0 or 1, of the type of the value
+	pub fn sign(&self) -> EVar {
+		match *self {
+			EVar::FVal(f1) => EVar::FVal(if f1 == 0.0 || f1.is_nan() {f1} else {f1.signum()}),
+			EVar::Null => EVar::Null,
+			_ => EVar::IVal(self.to_int().signum()),
+		}
+	}
+	pub fn log2(&self) -> EVar {
+		self.float_fn(f64::log2)
+	}
+	pub fn log(&self, base:& EVar) -> EVar {
+		if base.is_null() {
+			return EVar::Null;
+		}
+		self.float_fn(f64::ln).ev_div(&EVar::FVal(base.to_float().ln()))
+	}
+	pub fn hypot(&self, other:& EVar) -> EVar {
+		if self.is_null() || other.is_null() {
+			return EVar::Null;
+		}
+		EVar::FVal(self.to_float().hypot(other.to_float()))
+	}
 }
@@ -0,0 +1,103 @@
+use crate::*;
+
+// names of the stack commands
+const CMD_DUP:&str="dup";
+const CMD_SWAP:&str="swap";
+const CMD_DROP:&str="drop";
+const CMD_ROT:&str="rot";
+const CMD_CLEAR:&str="clear";
+const CMD_NEG:&str="neg";
+
+// stack calculator with postfix input (e.g. "3 4 + 2 *"), the stack being kept from one input to the next
+// - numbers, strings and constants are pushed on the stack; a sign right before a number (e.g. "-3") is
+// part of it, "- 3" or "-" alone being the operator
+// - operators and functions replace their parameters on top of the stack by their result, as in the
+// evaluation of an Expression; '!' and '~' take 1 parameter, the other operators 2, and the functions
+// with a variable number of parameters (e.g. max, sum) take the whole stack
+// - stack commands: dup, swap, drop, rot (the third value goes on top), clear, and neg (change of sign)
+#[derive(Default)]
+pub struct StackCalc {
+	stack:Vec<EVar>,
+}
+
+impl StackCalc {
+	pub fn new() -> StackCalc {
+		StackCalc::default()
+	}
+	// values of the stack, the top of the stack last
+	pub fn stack(&self) -> &[EVar] {
+		&self.stack
+	}
+	pub fn push(&mut self, val:EVar) {
+		self.stack.push(val);
+	}
+	pub fn clear(&mut self) {
+		self.stack.clear();
+	}
+	// evaluation of a postfix input, with the functions and constants of 'context'; returns the top of the stack
+	// if the input fails, the stack is left as it was before
+	pub fn eval(&mut self, input:&str, context:Option<&EvalContext>) -> Result<Option<EVar>, ExprError> {
+		let mut expr=Expression::empty(context);
+		expr.tokenize(input)?;
+		let promote=EvalContext::promote(context);
+		let mut stack=self.stack.clone();
+		let mut toks=expr.tokens.iter().peekable();
+		while let Some(tok)=toks.next() {
+			match tok.kind {
+				TokKind::Number | TokKind::Constant | TokKind::CString => stack.push(tok.val.clone()),
+				TokKind::Operator if (tok.refdef.name == "-" || tok.refdef.name == "+") &&
+					toks.peek().is_some_and(|next| next.kind == TokKind::Number && next.span.start == tok.span.end) => {
+					// signed number
+					let num=toks.next().unwrap();
+					stack.push(num.val.clone());
+					exec_fn(&tok.refdef.fn_eval, None, 1, &mut stack, promote).map_err(|e| ExprError::at(e, num))?;
+				},
+				TokKind::Operator => {
+					let nb_param=match tok.refdef.name {
+						"!" | "~" => 1,
+						TERNARY_OPEN | TERNARY_CLOSE => return Err(ExprError::at(RpnError::Exec(ExErr::StartWrongToken), tok)),
+						_ => 2,
+					};
+					exec_fn(&tok.refdef.fn_eval, None, nb_param, &mut stack, promote).map_err(|e| ExprError::at(e, tok))?;
+				},
+				TokKind::Function => {
+					let ufn=tok.ufn.and_then(|idx| context.and_then(|c| c.fns.get(idx)));
+					let params=ufn.map_or(tok.refdef.params, |f| f.params);
+					let nb_param=params.map_or(stack.len(), |n| n as usize);
+					let rv=match ufn {
+						Some(f) => exec_fn(&f.fn_eval, params, nb_param, &mut stack, promote),
+						None => exec_fn(&tok.refdef.fn_eval, params, nb_param, &mut stack, promote),
+					};
+					rv.map_err(|e| ExprError::at(e, tok))?;
+				},
+				// the names which are not functions nor constants can only be stack commands
+				TokKind::Variable => StackCalc::command(&tok.svalue, &mut stack, promote).map_err(|e| ExprError::at(e, tok))?,
+				_ => return Err(ExprError::at(RpnError::Exec(ExErr::StartWrongToken), tok)),
+			}
+		}
+		self.stack=stack;
+		Ok(self.stack.last().cloned())
+	}
+	fn command(name:&str, stack:&mut Vec<EVar>, promote:bool) -> Result<(), RpnError> {
+		let nb_param=match name {
+			CMD_DUP | CMD_DROP | CMD_NEG => 1,
+			CMD_SWAP => 2,
+			CMD_ROT => 3,
+			CMD_CLEAR => 0,
+			_ => return Err(RpnError::AnaLex(LexErr::FunctionOrConstNotFound)),
+		};
+		if stack.len() < nb_param {
+			return Err(RpnError::Exec(ExErr::GetOperandMissing));
+		}
+		let top=stack.len().saturating_sub(1);
+		match name {
+			CMD_DUP => stack.push(stack[top].clone()),
+			CMD_DROP => {stack.pop();},
+			CMD_NEG => exec_fn(&eval_sub, None, 1, stack, promote)?,
+			CMD_SWAP => stack.swap(top-1, top),
+			CMD_ROT => stack[top-2..].rotate_left(1),
+			_ => stack.clear(),
+		}
+		Ok(())
+	}
+}
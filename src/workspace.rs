@@ -0,0 +1,130 @@
+use crate::*;
+use crate::derived::*;
+use std::collections::BTreeSet;
+
+// expressions reading a value: definition index and variable slot in its expression
+type Readers=Vec<(usize, usize)>;
+
+// set of definitions kept evaluated while their inputs change: each change of an input marks the
+// definitions reading it, and an update evaluates only the marked definitions and, when their value
+// changes, the definitions using them (in dependency order)
+// - the definitions are evaluated a first time by the first update, those whose inputs are not all
+// set failing until they are
+// - a definition whose evaluation fails is null, its error being kept until it is evaluated again
+pub struct Workspace<'a> {
+	// in evaluation order, the definitions only using definitions before them
+	defs:Vec<Definition<'a>>,
+	values:Vec<EVar>,
+	errors:Vec<Option<DefError>>,
+	index:HashMap<String, usize>,
+	inputs:HashMap<String, (Option<EVar>, Readers)>,
+	readers:Vec<Readers>,
+	// definitions to evaluate at the next update
+	pending:BTreeSet<usize>,
+}
+
+impl <'a> Workspace <'a> {
+	pub fn new(defs:Definitions<'a>) -> Workspace<'a> {
+		let Definitions{mut defs, inputs}=defs;
+		let index:HashMap<String, usize>=defs.iter().enumerate().map(|(i, d)| (d.name.clone(), i)).collect();
+		let mut inputs:HashMap<String, (Option<EVar>, Readers)>=inputs.into_iter().map(|name| (name, (None, vec![]))).collect();
+		let mut readers=vec![vec![]; defs.len()];
+		for (i, def) in defs.iter_mut().enumerate() {
+			for slot in 0..def.expr.variables().len() {
+				let var=def.expr.variables()[slot].clone();
+				match index.get(&var) {
+					Some(&idef) => {
+						// the definitions are null until evaluated
+						readers[idef].push((i, slot));
+						let _=def.expr.set_slot(slot, EVar::Null);
+					},
+					None => if let Some(input)=inputs.get_mut(&var) {input.1.push((i, slot))},
+				}
+			}
+		}
+		let nb_def=defs.len();
+		Workspace{defs, values:vec![EVar::Null; nb_def], errors:vec![None; nb_def], index, inputs, readers, pending:(0..nb_def).collect()}
+	}
+	pub fn parse(text:&str, context:Option<&'a EvalContext>) -> Result<Workspace<'a>, DefError> {
+		Definitions::parse(text, context).map(Workspace::new)
+	}
+	// names of the definitions, in evaluation order
+	pub fn names(&self) -> Vec<&str> {
+		self.defs.iter().map(|d| d.name.as_str()).collect()
+	}
+	// names of the variables used by the definitions which are not defined
+	pub fn inputs(&self) -> Vec<&str> {
+		let mut names:Vec<&str>=self.inputs.keys().map(|n| n.as_str()).collect();
+		names.sort_unstable();
+		names
+	}
+	// change of an input, evaluated at the next update; returns false if the name is not an input
+	// a value identical to the current one (same type and same value) changes nothing
+	pub fn set(&mut self, name:&str, val:EVar) -> bool {
+		let Some((cur, readers))=self.inputs.get_mut(name) else {
+			return false;
+		};
+		if cur.as_ref().is_some_and(|cur| same(cur, &val)) {
+			return true;
+		}
+		for &(idef, slot) in readers.iter() {
+			let _=self.defs[idef].expr.set_slot(slot, val.clone());
+			self.pending.insert(idef);
+		}
+		*cur=Some(val);
+		true
+	}
+	// evaluation of the definitions affected by the changes since the previous update
+	// returns the names of the definitions whose value changed, in evaluation order
+	pub fn update(&mut self) -> Vec<String> {
+		let mut changed=vec![];
+		// the definitions using a definition come after it: they are evaluated once, after all their changes
+		while let Some(i)=self.pending.pop_first() {
+			let def=&mut self.defs[i];
+			let val=match def.expr.eval() {
+				Ok(val) => {
+					self.errors[i]=None;
+					val.unwrap_or(EVar::Null)
+				},
+				Err(e) => {
+					self.errors[i]=Some(DefError{error:DefErr::Expr(in_line(e, def.column)), name:def.name.clone(), line:def.line});
+					EVar::Null
+				},
+			};
+			if same(&self.values[i], &val) {
+				continue;
+			}
+			for &(idef, slot) in self.readers[i].iter() {
+				let _=self.defs[idef].expr.set_slot(slot, val.clone());
+				self.pending.insert(idef);
+			}
+			self.values[i]=val;
+			changed.push(self.defs[i].name.clone());
+		}
+		changed
+	}
+	// value of a definition (null before its first evaluation) or of an input
+	pub fn value(&self, name:&str) -> Option<&EVar> {
+		match self.index.get(name) {
+			Some(&i) => Some(&self.values[i]),
+			None => self.inputs.get(name).and_then(|input| input.0.as_ref()),
+		}
+	}
+	// error of the last evaluation of a definition
+	pub fn error(&self, name:&str) -> Option<&DefError> {
+		self.index.get(name).and_then(|&i| self.errors[i].as_ref())
+	}
+	// errors of the last evaluations of the definitions, in evaluation order
+	pub fn errors(&self) -> impl Iterator<Item=&DefError> {
+		self.errors.iter().flatten()
+	}
+}
+
+// a change from 1 to 1.0 or to true is a change, unlike for ==
+fn same(v1:&EVar, v2:&EVar) -> bool {
+	match (v1, v2) {
+		(EVar::Null, EVar::Null) => true,
+		(EVar::FVal(f1), EVar::FVal(f2)) => f1 == f2 || (f1.is_nan() && f2.is_nan()),
+		_ => std::mem::discriminant(v1) == std::mem::discriminant(v2) && v1 == v2,
+	}
+}
@@ -1,8 +1,12 @@
 use rpn;
 use crate::rpn::srlvariant::*;
 use crate::rpn::*;
-use crate::rpn::eval::*;
 use std::time::Instant;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::process::{Command, Stdio};
 
 struct RpnTest {
 	ctx:u32,
@@ -13,7 +17,7 @@ struct RpnTest {
 // 20211227 - perf tests
 // 1_000 times 86 test expression in 2.27 seconds, that is to say 26.3 µs/expression
 // rig: AMD Ryzen 7 3700X 8-Core Processor 3.60 GHz, 64 GB, Win 10 Pro 20H2, ASUS ROG Strix X570-I mini ITX
-const TESTS:[&'static RpnTest;86]=[
+const TESTS:[&'static RpnTest;193]=[
 	&RpnTest{ctx:0, expr:"pi", prec:0.0, expected:EVar::FVal(std::f64::consts::PI)},
 	&RpnTest{ctx:0, expr:"π", prec:0.0, expected:EVar::FVal(std::f64::consts::PI)},
 	&RpnTest{ctx:0, expr:"phi", prec:0.0, expected:EVar::FVal(1.618_033_988_749_894_848_204_586)},
@@ -102,19 +106,134 @@ const TESTS:[&'static RpnTest;86]=[
 	&RpnTest{ctx:0, expr:"sin(\"1.57079632679489661923132169163975144\")", prec:0.0, expected:EVar::FVal(1.0)},
 	// time functions
 	&RpnTest{ctx:0, expr:"now()-now()", prec:0.0, expected:EVar::IVal(0)},
+	// conditions
+	&RpnTest{ctx:0, expr:"if(1>2, 1/0, 5)", prec:0.0, expected:EVar::IVal(5)},
+	&RpnTest{ctx:0, expr:"if(1<2, 1., 1/0)", prec:0.0, expected:EVar::FVal(1.)},
+	&RpnTest{ctx:0, expr:"true ? 1 : 2", prec:0.0, expected:EVar::IVal(1)},
+	&RpnTest{ctx:0, expr:"1 > 2 ? 10 : 20 + 1", prec:0.0, expected:EVar::IVal(21)},
+	&RpnTest{ctx:0, expr:"false ? 1 : true ? 2 : 3", prec:0.0, expected:EVar::IVal(2)},
+	&RpnTest{ctx:0, expr:"false ? 1 : false ? 2 : 3", prec:0.0, expected:EVar::IVal(3)},
+	&RpnTest{ctx:0, expr:"true ? false ? 1 : 2 : 3", prec:0.0, expected:EVar::IVal(2)},
+	&RpnTest{ctx:0, expr:"max(1, 0?4:5, 2)*(true?2:1)", prec:0.0, expected:EVar::IVal(10)},
+	&RpnTest{ctx:0, expr:"false && 1/0 == 1", prec:0.0, expected:EVar::BVal(false)},
+	&RpnTest{ctx:0, expr:"true || 1/0", prec:0.0, expected:EVar::BVal(true)},
+	&RpnTest{ctx:0, expr:"2 && 0.5", prec:0.0, expected:EVar::BVal(true)},
+	// remainder, floor division and power
+	&RpnTest{ctx:0, expr:"17 % 5", prec:0.0, expected:EVar::IVal(2)},
+	&RpnTest{ctx:0, expr:"-17 % 5", prec:0.0, expected:EVar::IVal(-2)},
+	&RpnTest{ctx:0, expr:"7.5 % 2", prec:1e-12, expected:EVar::FVal(1.5)},
+	&RpnTest{ctx:0, expr:"17 // 5", prec:0.0, expected:EVar::IVal(3)},
+	&RpnTest{ctx:0, expr:"-17 // 5", prec:0.0, expected:EVar::IVal(-4)},
+	&RpnTest{ctx:0, expr:"-7.5 // 2", prec:0.0, expected:EVar::FVal(-4.0)},
+	&RpnTest{ctx:0, expr:"1 + 10 // 3", prec:0.0, expected:EVar::IVal(4)},
+	// same priority as '*', from left to right
+	&RpnTest{ctx:0, expr:"7 % 4 * 2", prec:0.0, expected:EVar::IVal(6)},
+	&RpnTest{ctx:0, expr:"10 // 3 * 2", prec:0.0, expected:EVar::IVal(6)},
+	&RpnTest{ctx:0, expr:"2 * 7 % 4", prec:0.0, expected:EVar::IVal(2)},
+	&RpnTest{ctx:0, expr:"12 / 3 * 2", prec:0.0, expected:EVar::IVal(8)},
+	&RpnTest{ctx:0, expr:"2 ** 10", prec:0.0, expected:EVar::IVal(1024)},
+	&RpnTest{ctx:0, expr:"pow(1, 5000000000)", prec:0.0, expected:EVar::IVal(1)},
+	&RpnTest{ctx:0, expr:"0 ** 5000000000", prec:0.0, expected:EVar::IVal(0)},
+	&RpnTest{ctx:0, expr:"(-1) ** 5000000000", prec:0.0, expected:EVar::IVal(1)},
+	&RpnTest{ctx:0, expr:"(-1) ** 5000000001", prec:0.0, expected:EVar::IVal(-1)},
+	&RpnTest{ctx:0, expr:"2 ** 3 ** 2", prec:0.0, expected:EVar::IVal(512)},
+	&RpnTest{ctx:0, expr:"-2 ** 2", prec:0.0, expected:EVar::IVal(-4)},
+	&RpnTest{ctx:0, expr:"(-2) ** 2", prec:0.0, expected:EVar::IVal(4)},
+	&RpnTest{ctx:0, expr:"2 ** -1", prec:0.0, expected:EVar::FVal(0.5)},
+	&RpnTest{ctx:0, expr:"3 * 2 ** 2 + 1", prec:0.0, expected:EVar::IVal(13)},
+	&RpnTest{ctx:0, expr:"4 ** 0.5 - 2 * -3", prec:1e-12, expected:EVar::FVal(8.0)},
+	// rounding and numeric functions
+	&RpnTest{ctx:0, expr:"abs(-3)", prec:0.0, expected:EVar::IVal(3)},
+	&RpnTest{ctx:0, expr:"abs(-2.5)", prec:0.0, expected:EVar::FVal(2.5)},
+	&RpnTest{ctx:0, expr:"sign(-7) + sign(0)", prec:0.0, expected:EVar::IVal(-1)},
+	&RpnTest{ctx:0, expr:"sign(0.3)", prec:0.0, expected:EVar::FVal(1.0)},
+	&RpnTest{ctx:0, expr:"round(1.23456, 2)", prec:1e-12, expected:EVar::FVal(1.23)},
+	&RpnTest{ctx:0, expr:"round(-2.5, 0)", prec:0.0, expected:EVar::FVal(-3.0)},
+	&RpnTest{ctx:0, expr:"round(1234.5, -2)", prec:0.0, expected:EVar::FVal(1200.0)},
+	&RpnTest{ctx:0, expr:"round(1250, -2)", prec:0.0, expected:EVar::IVal(1300)},
+	&RpnTest{ctx:0, expr:"round(-1249, -2)", prec:0.0, expected:EVar::IVal(-1200)},
+	&RpnTest{ctx:0, expr:"round(17, 2)", prec:0.0, expected:EVar::IVal(17)},
+	&RpnTest{ctx:0, expr:"floor(-2.5)", prec:0.0, expected:EVar::FVal(-3.0)},
+	&RpnTest{ctx:0, expr:"ceil(2.1)", prec:0.0, expected:EVar::FVal(3.0)},
+	&RpnTest{ctx:0, expr:"floor(7)", prec:0.0, expected:EVar::IVal(7)},
+	&RpnTest{ctx:0, expr:"trunc(-2.7)", prec:0.0, expected:EVar::FVal(-2.0)},
+	&RpnTest{ctx:0, expr:"frac(-2.75)", prec:0.0, expected:EVar::FVal(-0.75)},
+	&RpnTest{ctx:0, expr:"frac(4)", prec:0.0, expected:EVar::IVal(0)},
+	&RpnTest{ctx:0, expr:"clamp(12, 0, 10)", prec:0.0, expected:EVar::IVal(10)},
+	&RpnTest{ctx:0, expr:"clamp(-0.5, 0, 1.5)", prec:0.0, expected:EVar::FVal(0.0)},
+	&RpnTest{ctx:0, expr:"clamp(7, 0, 10)", prec:0.0, expected:EVar::IVal(7)},
+	&RpnTest{ctx:0, expr:"hypot(3, 4)", prec:0.0, expected:EVar::FVal(5.0)},
+	&RpnTest{ctx:0, expr:"log(8, 2)", prec:1e-12, expected:EVar::FVal(3.0)},
+	&RpnTest{ctx:0, expr:"log2(1024)", prec:0.0, expected:EVar::FVal(10.0)},
+	// inverse and hyperbolic trigonometry
+	&RpnTest{ctx:0, expr:"asin(1)", prec:1e-12, expected:EVar::FVal(std::f64::consts::FRAC_PI_2)},
+	&RpnTest{ctx:0, expr:"acos(1)", prec:0.0, expected:EVar::FVal(0.0)},
+	&RpnTest{ctx:0, expr:"atan(1)*4", prec:1e-12, expected:EVar::FVal(std::f64::consts::PI)},
+	&RpnTest{ctx:0, expr:"atan2(1, -1)", prec:1e-12, expected:EVar::FVal(3.0*std::f64::consts::FRAC_PI_4)},
+	&RpnTest{ctx:0, expr:"cosh(0) + sinh(0) + tanh(0)", prec:0.0, expected:EVar::FVal(1.0)},
+	&RpnTest{ctx:0, expr:"asinh(sinh(0.5))", prec:1e-12, expected:EVar::FVal(0.5)},
+	&RpnTest{ctx:0, expr:"acosh(cosh(2))", prec:1e-12, expected:EVar::FVal(2.0)},
+	&RpnTest{ctx:0, expr:"atanh(tanh(0.3))", prec:1e-12, expected:EVar::FVal(0.3)},
+	&RpnTest{ctx:0, expr:"deg(pi/2)", prec:1e-12, expected:EVar::FVal(90.0)},
+	&RpnTest{ctx:0, expr:"rad(180)", prec:1e-12, expected:EVar::FVal(std::f64::consts::PI)},
+	// statistics
+	&RpnTest{ctx:0, expr:"sum(1, 2, 3, 4)", prec:0.0, expected:EVar::IVal(10)},
+	&RpnTest{ctx:0, expr:"sum(1, 2.5)", prec:0.0, expected:EVar::FVal(3.5)},
+	&RpnTest{ctx:0, expr:"prod(2, 3, 4)", prec:0.0, expected:EVar::IVal(24)},
+	&RpnTest{ctx:0, expr:"count(4, null, 5)", prec:0.0, expected:EVar::IVal(2)},
+	&RpnTest{ctx:0, expr:"median(20, 35, 21)", prec:0.0, expected:EVar::IVal(21)},
+	&RpnTest{ctx:0, expr:"median(4, 1, 3, 2)", prec:0.0, expected:EVar::FVal(2.5)},
+	&RpnTest{ctx:0, expr:"median(7.5, null, 2)", prec:0.0, expected:EVar::FVal(4.75)},
+	&RpnTest{ctx:0, expr:"variance(2, 4, 4, 4, 5, 5, 7, 9)", prec:1e-12, expected:EVar::FVal(4.0)},
+	&RpnTest{ctx:0, expr:"stddev(2, 4, 4, 4, 5, 5, 7, 9)", prec:1e-12, expected:EVar::FVal(2.0)},
+	&RpnTest{ctx:0, expr:"range(3, -2, 8)", prec:0.0, expected:EVar::IVal(10)},
+	&RpnTest{ctx:0, expr:"mode(1, 3, 2, 3, 1, 3)", prec:0.0, expected:EVar::IVal(3)},
+	&RpnTest{ctx:0, expr:"percentile(50, 1, 2, 3, 4)", prec:1e-12, expected:EVar::FVal(2.5)},
+	&RpnTest{ctx:0, expr:"percentile(90, 10, 20, 30, 40, 50)", prec:1e-12, expected:EVar::FVal(46.0)},
+	&RpnTest{ctx:0, expr:"percentile(100, 5, 1, 3)", prec:0.0, expected:EVar::FVal(5.0)},
+	// bits and integers
+	&RpnTest{ctx:0, expr:"bit(10, 1)", prec:0.0, expected:EVar::BVal(true)},
+	&RpnTest{ctx:0, expr:"bit(0x8000, 14)", prec:0.0, expected:EVar::BVal(false)},
+	&RpnTest{ctx:0, expr:"setbit(0x10, 0)", prec:0.0, expected:EVar::IVal(0x11)},
+	&RpnTest{ctx:0, expr:"clrbit(0xff, 7)", prec:0.0, expected:EVar::IVal(0x7f)},
+	&RpnTest{ctx:0, expr:"bits(0xabcd, 4, 11)", prec:0.0, expected:EVar::IVal(0xbc)},
+	&RpnTest{ctx:0, expr:"bits(-1, 0, 63)", prec:0.0, expected:EVar::IVal(-1)},
+	&RpnTest{ctx:0, expr:"popcount(0xf0f0)", prec:0.0, expected:EVar::IVal(8)},
+	&RpnTest{ctx:0, expr:"rotl(0x81, 1, 8)", prec:0.0, expected:EVar::IVal(0x03)},
+	&RpnTest{ctx:0, expr:"rotr(0x0001, 4, 16)", prec:0.0, expected:EVar::IVal(0x1000)},
+	&RpnTest{ctx:0, expr:"rotl(0x1234, 20, 16)", prec:0.0, expected:EVar::IVal(0x2341)},
+	&RpnTest{ctx:0, expr:"bswap16(0x1234)", prec:0.0, expected:EVar::IVal(0x3412)},
+	&RpnTest{ctx:0, expr:"bswap32(0x12345678)", prec:0.0, expected:EVar::IVal(0x78563412)},
+	&RpnTest{ctx:0, expr:"bswap64(0x0102030405060708)", prec:0.0, expected:EVar::IVal(0x0807060504030201)},
+	&RpnTest{ctx:0, expr:"gcd(48, -18)", prec:0.0, expected:EVar::IVal(6)},
+	&RpnTest{ctx:0, expr:"lcm(4, 6)", prec:0.0, expected:EVar::IVal(12)},
+	&RpnTest{ctx:0, expr:"factorial(5)", prec:0.0, expected:EVar::IVal(120)},
+	&RpnTest{ctx:0, expr:"factorial(0)", prec:0.0, expected:EVar::IVal(1)},
+	// registers: 123.456 as a f32 is 0x42f6e979, -123456 as a i32 is 0xfffe1dc0
+	&RpnTest{ctx:0, expr:"f32_from_regs(0x42f6, 0xe979)", prec:1e-4, expected:EVar::FVal(123.456)},
+	&RpnTest{ctx:0, expr:"f32_from_regs_cdab(0xe979, 0x42f6)", prec:1e-4, expected:EVar::FVal(123.456)},
+	&RpnTest{ctx:0, expr:"f32_from_regs_badc(0xf642, 0x79e9)", prec:1e-4, expected:EVar::FVal(123.456)},
+	&RpnTest{ctx:0, expr:"f32_from_regs_dcba(0x79e9, 0xf642)", prec:1e-4, expected:EVar::FVal(123.456)},
+	&RpnTest{ctx:0, expr:"i32_from_regs(0xfffe, 0x1dc0)", prec:0.0, expected:EVar::IVal(-123456)},
+	&RpnTest{ctx:0, expr:"i32_from_regs_cdab(0x1dc0, 0xfffe)", prec:0.0, expected:EVar::IVal(-123456)},
+	&RpnTest{ctx:0, expr:"u32_from_regs(0xfffe, 0x1dc0)", prec:0.0, expected:EVar::IVal(0xfffe1dc0)},
+	&RpnTest{ctx:0, expr:"u32_from_regs_dcba(0xc01d, 0xfeff)", prec:0.0, expected:EVar::IVal(0xfffe1dc0)},
+	&RpnTest{ctx:0, expr:"i16(0xff38)", prec:0.0, expected:EVar::IVal(-200)},
+	&RpnTest{ctx:0, expr:"u16(-200)", prec:0.0, expected:EVar::IVal(0xff38)},
+	&RpnTest{ctx:0, expr:"bcd(0x1234)", prec:0.0, expected:EVar::IVal(1234)},
 	// context - user-defined functions
 	&RpnTest{ctx:1, expr:"var(42)", prec:0.0, expected:EVar::IVal(42)},
 	&RpnTest{ctx:1, expr:"var(42.42)", prec:0.0, expected:EVar::FVal(42.42)},	
 ];
 
 const USER_DEF:[FuCoOpDef;1]=[
-	FuCoOpDef{fn_eval:eval_test,  params:Some(1), name:"var", prio:0, val:EVar::IVal(0)},
+	FuCoOpDef{fn_eval:eval_test,  params:Some(1), name:"var", prio:0, val:EVar::IVal(0), pure:false},
 ];
 
 
 #[test]
 pub fn rpn_test() {
-	let context_ex=EvalContext{user_fns:& USER_DEF};
+	let context_ex=EvalContext::with_user_fns(& USER_DEF);
 	let start = Instant::now();
 	for _ in 0..1 {
 		for test in TESTS {
@@ -122,7 +241,7 @@ pub fn rpn_test() {
 			if let Ok(rv)=toks.eval() {
 				if let Some(res)=rv {
 					match test.expected {
-						EVar::SVal(_) => 
+						EVar::SVal(_) | EVar::Null => 
 							assert!(false, "eval returned wrong type for {} {:?} {:?}", test.expr, res, test.expected),
 						EVar::IVal(i) => 
 							if let EVar::IVal(ires)=res {
@@ -160,9 +279,384 @@ pub fn rpn_test() {
 	println!("elapsed for rpn_test {:#?}", start.elapsed());
 }
 
-fn eval_test(i:usize, tokens:& Vec<Token>, _:u32) -> Result<EVar, RpnError> {
-	let (_, op1) = get_operand (i, tokens)?;
-	Ok(op1.val.clone())
+#[test]
+pub fn rpn_test_variables() {
+	let mut vars=HashMap::new();
+	vars.insert("level".to_string(), EVar::FVal(2.5));
+	vars.insert("tank_volume".to_string(), EVar::IVal(4));
+	let mut expr=rpn::Expression::new("level*tank_volume+max(level,3)", None);
+	assert!(expr.eval_with(&vars).ok() == Some(Some(EVar::FVal(13.0))));
+	vars.insert("level".to_string(), EVar::IVal(5));
+	assert!(expr.eval_with(&vars).ok() == Some(Some(EVar::IVal(25))));
+	// a single variable is a valid expression
+	let mut expr=rpn::Expression::new("level", None);
+	assert!(expr.eval_with(&vars).ok() == Some(Some(EVar::IVal(5))));
+	// unbound variable
+	let mut expr=rpn::Expression::new("level+unknown", None);
+	assert!(expr.eval_with(&vars).is_err());
+	assert!(expr.eval().is_err());
+	// parse once, rebind the slots and evaluate many times
+	let mut expr=rpn::Expression::new("a*x+b", None);
+	assert!(expr.variables() == ["a", "x", "b"]);
+	let x=expr.var_slot("x").unwrap();
+	assert!(expr.set_var("a", EVar::IVal(2)).is_ok() && expr.set_var("b", EVar::IVal(1)).is_ok());
+	assert!(expr.eval().is_err());
+	for i in 0..10 {
+		assert!(expr.set_slot(x, EVar::IVal(i)).is_ok());
+		assert!(expr.eval().ok() == Some(Some(EVar::IVal(2*i+1))));
+	}
+	assert!(expr.set_var("y", EVar::IVal(0)).is_err());
+	// an unknown name followed by a parenthesis is still an unknown function
+	let mut expr=rpn::Expression::new("sinn(level)", None);
+	assert!(expr.eval_with(&vars).is_err());
+}
+
+#[test]
+pub fn rpn_test_compiled() {
+	let context_ex=EvalContext::with_user_fns(& USER_DEF);
+	// the compiled form gives the same results as the expression it is compiled from
+	for test in TESTS {
+		let mut expr=rpn::Expression::new(test.expr, if test.ctx == 1 {Some(&context_ex)} else {None});
+		let compiled=expr.compile();
+		assert!(compiled.is_ok(), "'{}' could not be compiled", test.expr);
+		let res=compiled.ok().unwrap().eval(&[]);
+		assert!(res.is_ok() && expr.eval().ok() == Some(res.ok()), "compiled test failed for {}", test.expr);
+	}
+	let expr=rpn::Expression::new("a*x+b", None);
+	let compiled=expr.compile().ok().unwrap();
+	assert!(compiled.variables() == ["a", "x", "b"]);
+	assert!(compiled.eval(&[EVar::IVal(2), EVar::IVal(3), EVar::IVal(1)]).ok() == Some(EVar::IVal(7)));
+	assert!(compiled.eval(&[EVar::IVal(2)]).is_err());
+}
+
+#[test]
+pub fn rpn_test_constant_folding() {
+	let context_ex=EvalContext::with_user_fns(& USER_DEF);
+	let expr=rpn::Expression::new("2*pi", None);
+	assert!(expr.display_parsed() == "6.283185307179586 ");
+	let expr=rpn::Expression::new("x*(1+sqrt(4))-max(x,2*3)", None);
+	assert!(expr.display_parsed() == "x 3 *(bi) x 6 max(2) -(bi) ");
+	// impure functions are not folded
+	let expr=rpn::Expression::new("now()-now()", None);
+	assert!(expr.display_parsed() == "now(0) now(0) -(bi) ");
+	let expr=rpn::Expression::new("var(1+1)", Some(&context_ex));
+	assert!(expr.display_parsed() == "2 var(1) ");
+	// a failing sub-expression is kept, the error is raised at evaluation
+	let mut expr=rpn::Expression::new("1+1/0", None);
+	assert!(expr.display_parsed() == "1 1 0 /(bi) +(bi) ");
+	assert!(expr.eval().is_err());
+}
+
+#[test]
+pub fn rpn_test_registered_fns() {
+	// closures capturing host state
+	let table=[10, 20, 30];
+	let calls=Rc::new(Cell::new(0));
+	let counter=Rc::clone(&calls);
+	let mut context=EvalContext::new();
+	context.register_fn("lookup", Some(1), move |params| {
+		match table.get(params[0].to_int() as usize) {
+			Some(v) => Ok(EVar::IVal(*v)),
+			None => Err(RpnError::Exec(ExErr::EvalNullFn)),
+		}
+	});
+	context.register_fn("count", Some(0), move |_| {
+		counter.set(counter.get()+1);
+		Ok(EVar::IVal(counter.get()))
+	});
+	context.register_fn("sum", None, |params| Ok(params.iter().fold(EVar::IVal(0), |acc, p| acc+p.clone())));
+	let mut expr=rpn::Expression::new("lookup(1)+lookup(x)", Some(&context));
+	assert!(expr.set_var("x", EVar::IVal(2)).is_ok());
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(50))));
+	assert!(expr.set_var("x", EVar::IVal(3)).is_ok());
+	assert!(expr.eval().is_err());
+	let mut expr=rpn::Expression::new("count()+sum(1,2,3,4)", Some(&context));
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(11))));
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(12))));
+	let compiled=expr.compile().ok().unwrap();
+	assert!(compiled.eval(&[]).ok() == Some(EVar::IVal(13)));
+	assert!(calls.get() == 3);
+	// wrong number of parameters
+	let mut expr=rpn::Expression::new("lookup(1,2)", Some(&context));
+	assert!(expr.eval().is_err());
+}
+
+#[test]
+pub fn rpn_test_user_consts() {
+	let mut context=EvalContext::new();
+	context.register_const("tank_volume", EVar::FVal(12.5));
+	context.register_const("k", EVar::IVal(3));
+	context.register_const("k", EVar::IVal(4));
+	let mut expr=rpn::Expression::new("level*tank_volume/k", Some(&context));
+	assert!(expr.variables() == ["level"]);
+	assert!(expr.set_var("level", EVar::FVal(0.5)).is_ok());
+	assert!(expr.eval().ok() == Some(Some(EVar::FVal(1.5625))));
+	// the registered constants hide the built-in ones
+	context.register_const("h", EVar::FVal(2.5));
+	context.register_const("e", EVar::IVal(2));
+	let mut expr=rpn::Expression::new("h*e + c", Some(&context));
+	assert!(expr.eval().ok() == Some(Some(EVar::FVal(299_792_463.0))));
+	// constants are not variables in an expression without context
+	let expr=rpn::Expression::new("level*tank_volume/k", None);
+	assert!(expr.variables() == ["level", "tank_volume", "k"]);
+}
+
+#[test]
+pub fn rpn_test_conditions() {
+	let mut expr=rpn::Expression::new("x != 0 ? y/x : 0", None);
+	let compiled=expr.compile().ok().unwrap();
+	assert!(expr.set_var("x", EVar::IVal(0)).is_ok() && expr.set_var("y", EVar::IVal(4)).is_ok());
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(0))));
+	assert!(compiled.eval(&[EVar::IVal(0), EVar::IVal(4)]).ok() == Some(EVar::IVal(0)));
+	assert!(expr.set_var("x", EVar::IVal(2)).is_ok());
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(2))));
+	assert!(compiled.eval(&[EVar::IVal(2), EVar::IVal(4)]).ok() == Some(EVar::IVal(2)));
+	// short-circuit
+	let mut expr=rpn::Expression::new("x > 0 && y/x > 1 || if(x == 0, y, 1/x) == 4", None);
+	for (x, res) in [(0, true), (1, true), (8, false)] {
+		assert!(expr.set_var("x", EVar::IVal(x)).is_ok() && expr.set_var("y", EVar::IVal(4)).is_ok());
+		assert!(expr.eval().ok() == Some(Some(EVar::BVal(res))), "short-circuit failed for x={}", x);
+	}
+	// a '?' without its ':' and the reverse
+	for exp in ["x ? 1", "x : 1", "max(x ? 1, 2)", "(x ? 1) : 2"] {
+		let mut expr=rpn::Expression::new(exp, None);
+		assert!(expr.eval_with(&HashMap::from([("x".to_string(), EVar::IVal(1))])).is_err(), "'{}' should fail", exp);
+	}
+}
+
+#[test]
+pub fn rpn_test_errors() {
+	let tests=[
+		("1+sinn(2)", RpnError::AnaLex(LexErr::FunctionOrConstNotFound), "unknown function 'sinn' at column 3"),
+		("1+sinn (2)", RpnError::AnaLex(LexErr::FunctionOrConstNotFound), "unknown function 'sinn' at column 3"),
+		("2 # 3", RpnError::AnaLex(LexErr::BadStart), "unexpected character '#' at column 3"),
+		("π*2+ 0xfg", RpnError::AnaLex(LexErr::BadHex), "invalid hexadecimal number '0xf' at column 6"),
+		("pow(2, 3, 4)", RpnError::Parse(ParErr::ParamNb), "wrong number of parameters for 'pow' at column 1"),
+		("(1+2))", RpnError::Parse(ParErr::MatchingPar), "unbalanced parenthesis ')' at column 6"),
+		("x ? 1", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator '?' at column 3"),
+		("1 ? : 2", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 5"),
+		("0 ? : 2", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 5"),
+		("x ? : 2", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 5"),
+		("x ? 1 :", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 7"),
+		("max(x ? 1 :, 2)", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 11"),
+		("(x ? 1 :) + 2", RpnError::Parse(ParErr::MatchingTernary), "unbalanced '?' and ':' of ternary operator ':' at column 8"),
+		("1 + 2/(x-1)", RpnError::Exec(ExErr::EvalDiv0), "division by zero in '/' at column 6"),
+		("x + y", RpnError::Exec(ExErr::VariableNotFound), "no value for variable 'y' at column 5"),
+		("1+", RpnError::Exec(ExErr::GetOperandMissing), "missing operand for '+' at column 2"),
+		("3 -", RpnError::Exec(ExErr::GetOperandMissing), "missing operand for '-' at column 3"),
+		("2*", RpnError::Exec(ExErr::GetOperandMissing), "missing operand for '*' at column 2"),
+		("2/", RpnError::Exec(ExErr::GetOperandMissing), "missing operand for '/' at column 2"),
+		("x*", RpnError::Exec(ExErr::GetOperandMissing), "missing operand for '*' at column 2"),
+		("*x", RpnError::Parse(ParErr::MissingOperand), "missing left operand for '*' at column 1"),
+		("==x", RpnError::Parse(ParErr::MissingOperand), "missing left operand for '==' at column 1"),
+		("*3", RpnError::Parse(ParErr::MissingOperand), "missing left operand for '*' at column 1"),
+		("<3", RpnError::Parse(ParErr::MissingOperand), "missing left operand for '<' at column 1"),
+		("max(1, *x)", RpnError::Parse(ParErr::MissingOperand), "missing left operand for '*' at column 8"),
+		("x+sqrt", RpnError::Parse(ParErr::ParamNb), "wrong number of parameters for 'sqrt' at column 3"),
+		("sin", RpnError::Parse(ParErr::ParamNb), "wrong number of parameters for 'sin' at column 1"),
+		("1+sqrt", RpnError::Parse(ParErr::ParamNb), "wrong number of parameters for 'sqrt' at column 3"),
+		("x + pow(10, 30)", RpnError::Exec(ExErr::Overflow), "integer overflow in 'pow' at column 5"),
+		("x % (x - 1)", RpnError::Exec(ExErr::EvalDiv0), "division by zero in '%' at column 3"),
+		("1 + median()", RpnError::Exec(ExErr::EvalStatParam), "no parameter for 'median' at column 5"),
+		("percentile(101, x, 2)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'percentile' at column 1"),
+		("bit(x, 64)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'bit' at column 1"),
+		("rotl(x, 1, 65)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'rotl' at column 1"),
+		("rotl(1, 1, -9223372036854775807-1)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'rotl' at column 1"),
+		("rotr(x, 1, 0)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'rotr' at column 1"),
+		("bcd(x + 0x1a)", RpnError::Exec(ExErr::EvalBadParam), "parameter out of range for 'bcd' at column 1"),
+		("factorial(x + 20)", RpnError::Exec(ExErr::Overflow), "integer overflow in 'factorial' at column 1"),
+		("1 + \"abc", RpnError::AnaLex(LexErr::UnterminatedString), "unterminated string '\"abc' at column 5"),
+		("'abc\\q'", RpnError::AnaLex(LexErr::BadEscape), "invalid escape sequence in string ''abc\\' at column 1"),
+		("'\\u{d800}'", RpnError::AnaLex(LexErr::BadEscape), "invalid escape sequence in string ''\\u{d800' at column 1"),
+	];
+	for (exp, error, msg) in tests {
+		let mut expr=rpn::Expression::new(exp, None);
+		let rv=expr.eval_with(&HashMap::from([("x".to_string(), EVar::IVal(1))]));
+		assert!(rv.is_err(), "'{}' should fail", exp);
+		let e=rv.err().unwrap();
+		assert!(e.error == error && format!("{}", e) == msg, "wrong error for '{}': {}", exp, e);
+		assert!(expr.result() == msg);
+	}
+	// the number of parameters of a function is checked at execution too
+	let mut stack=vec![EVar::IVal(4), EVar::IVal(9)];
+	assert!(rpn::eval::exec_fn(&rpn::eval::eval_sqrt, Some(1), 0, &mut stack, false) == Err(RpnError::Exec(ExErr::GetOperandMissing)));
+	assert!(rpn::eval::exec_fn(&rpn::eval::eval_sqrt, Some(1), 2, &mut stack, false) == Err(RpnError::Exec(ExErr::TooManyParams)));
+	assert!(rpn::eval::exec_fn(&rpn::eval::eval_sqrt, Some(1), 1, &mut stack, false).is_ok() && stack[1] == EVar::FVal(3.0));
+}
+
+#[test]
+pub fn rpn_test_trace() {
+	let messages=Rc::new(RefCell::new(Vec::<String>::new()));
+	let log=Rc::clone(&messages);
+	let mut context=EvalContext::new();
+	context.set_trace(move |msg| log.borrow_mut().push(msg.to_string()));
+	let _=rpn::Expression::new("1+2*x", Some(&context));
+	let _=rpn::Expression::new("1+(2", Some(&context));
+	let messages=messages.borrow();
+	assert!(messages.iter().any(|m| m == "expression '1+2*x' successully parsed as '1 2 x *(bi) +(bi) '"));
+	assert!(messages.iter().any(|m| m == "expression '1+(2' could not be parsed with error unbalanced parenthesis '(' at column 3"));
+}
+
+#[test]
+pub fn rpn_test_strings() {
+	let tests=[
+		("\"abc\" + \"def\"", "abcdef"),
+		("\"level: \" + 2.5 + \" m\"", "level: 2.5 m"),
+		("upper(\"Pompe n°\") + 3", "POMPE N°3"),
+		("lower(\"ALARM\")", "alarm"),
+		("trim(\"  idle \")", "idle"),
+		("substr(\"température\", 0, 4)", "temp"),
+		("substr(\"température\", 8, 10)", "ure"),
+		("replace(\"a-b-c\", \"-\", \"+\")", "a+b+c"),
+		("state == 1 ? \"running\" : \"stopped\"", "running"),
+		("\"state \" + state", "state 1"),
+		// escapes and single quotes
+		("'single' + ' \"quoted\"'", "single \"quoted\""),
+		("\"a\\\"b\\\\c\"", "a\"b\\c"),
+		("'it\\'s'", "it's"),
+		("\"l1\\nl2\\tx\"", "l1\nl2\tx"),
+		("\"\\u{3c0}r\\u{B2}\"", "πr²"),
+	];
+	let vars=HashMap::from([("state".to_string(), EVar::IVal(1))]);
+	for (exp, res) in tests {
+		let mut expr=rpn::Expression::new(exp, None);
+		let rv=expr.eval_with(&vars);
+		assert!(matches!(rv, Ok(Some(EVar::SVal(_)))) && expr.result() == res, "test failed for {}: {}", exp, expr.result());
+	}
+	let tests=[
+		("len(\"température\")", EVar::IVal(11)),
+		("find(\"température\", \"ure\")", EVar::IVal(8)),
+		("find(\"abc\", \"d\")", EVar::IVal(-1)),
+		("startswith(\"ALM_01\", \"ALM\")", EVar::BVal(true)),
+		("endswith(\"ALM_01\", \"ALM\")", EVar::BVal(false)),
+		("\"abc\" == \"abc\"", EVar::BVal(true)),
+		("\"abc\" != \"abd\"", EVar::BVal(true)),
+		("\"abc\" < \"abd\"", EVar::BVal(true)),
+		("\"b\" >= \"abc\"", EVar::BVal(true)),
+		// a string and a number
+		("\"abc\" == 0", EVar::BVal(false)),
+		("\"abc\" != 0", EVar::BVal(true)),
+		("\"abc\" < 1", EVar::BVal(false)),
+		("\"abc\" >= 0", EVar::BVal(false)),
+		("1 > \"abc\"", EVar::BVal(false)),
+		("\"1\" == 1", EVar::BVal(false)),
+	];
+	for (exp, res) in tests {
+		let mut expr=rpn::Expression::new(exp, None);
+		assert!(expr.eval().ok() == Some(Some(res)), "test failed for {}: {}", exp, expr.result());
+	}
+}
+
+#[test]
+pub fn rpn_test_null() {
+	// 'level' is not available
+	let tests=[
+		("level + 1", EVar::Null),
+		("level * 2.5 - 1", EVar::Null),
+		("level / 0", EVar::Null),
+		("sqrt(level)", EVar::Null),
+		("level & 3", EVar::Null),
+		("upper(level)", EVar::Null),
+		("isnull(level)", EVar::BVal(true)),
+		("isnull(temp)", EVar::BVal(false)),
+		("isnull(null)", EVar::BVal(true)),
+		("level == null", EVar::BVal(true)),
+		("level == 0", EVar::BVal(false)),
+		("level < 1", EVar::BVal(false)),
+		("coalesce(level, temp, 0)", EVar::FVal(12.5)),
+		("coalesce(level, null)", EVar::Null),
+		("avg(level, temp, 7.5)", EVar::FVal(10.0)),
+		("avg(level, null)", EVar::Null),
+		("max(level, 2, temp)", EVar::FVal(12.5)),
+		("min(3, level)", EVar::IVal(3)),
+		("isnull(level) ? -1 : level", EVar::IVal(-1)),
+	];
+	let vars=HashMap::from([("level".to_string(), EVar::Null), ("temp".to_string(), EVar::FVal(12.5))]);
+	for (exp, res) in tests {
+		let mut expr=rpn::Expression::new(exp, None);
+		assert!(expr.eval_with(&vars).ok() == Some(Some(res)), "test failed for {}: {}", exp, expr.result());
+	}
+	let mut expr=rpn::Expression::new("null", None);
+	assert!(expr.eval().is_ok() && expr.result() == "null");
+}
+
+#[test]
+pub fn rpn_test_overflow() {
+	let tests=[
+		"pow(10, 30)",
+		"x * 9223372036854775807",
+		"x + 9223372036854775807",
+		"-x - 9223372036854775807 - 2",
+		"-(x - 9223372036854775807 - 3)",
+		"(x - 9223372036854775807 - 3) / (x - 3)",
+		"x << 64",
+		"x >> -1",
+		"abs(-9223372036854775807 - x + 1)",
+	];
+	let vars=HashMap::from([("x".to_string(), EVar::IVal(2))]);
+	for exp in tests {
+		let mut expr=rpn::Expression::new(exp, None);
+		let rv=expr.eval_with(&vars);
+		assert!(rv.err().map(|e| e.error) == Some(RpnError::Exec(ExErr::Overflow)), "'{}' should overflow", exp);
+	}
+	// with promotion, the overflowing operation is done with floats
+	let mut context=EvalContext::new();
+	context.set_overflow_promote(true);
+	let tests=[
+		("pow(10, 30)", EVar::FVal(1e30)),
+		("x * 9223372036854775807", EVar::FVal(2.0*9223372036854775807.0)),
+		("x * 3 + 1", EVar::IVal(7)),
+	];
+	for (exp, res) in tests {
+		let mut expr=rpn::Expression::new(exp, Some(&context));
+		assert!(expr.eval_with(&vars).ok() == Some(Some(res.clone())), "test failed for {}: {}", exp, expr.result());
+		let slots=[EVar::IVal(2)];
+		assert!(expr.compile().unwrap().eval(&slots[..expr.variables().len()]).ok() == Some(res), "compiled test failed for {}", exp);
+	}
+	assert!(rpn::Expression::new("x << 64", Some(&context)).eval_with(&vars).is_err());
+}
+
+#[test]
+pub fn rpn_test_degrees() {
+	let mut context=EvalContext::new();
+	context.set_degrees(true);
+	let tests=[
+		("sin(30)", 0.5),
+		("cos(60) + tan(45)", 1.5),
+		("asin(0.5)", 30.0),
+		("acos(0)", 90.0),
+		("atan(1)", 45.0),
+		("atan2(ay, ax)", 135.0),
+		("sinh(0) + deg(pi)", 180.0),
+	];
+	let vars=HashMap::from([("ax".to_string(), EVar::FVal(-0.7)), ("ay".to_string(), EVar::FVal(0.7))]);
+	for (exp, res) in tests {
+		let mut expr=rpn::Expression::new(exp, Some(&context));
+		let rv=expr.eval_with(&vars);
+		assert!(matches!(rv, Ok(Some(EVar::FVal(f))) if (f-res).abs() < 1e-9), "test failed for {}: {}", exp, expr.result());
+	}
+	// radians without the option
+	context.set_degrees(false);
+	let mut expr=rpn::Expression::new("sin(30)", Some(&context));
+	assert!(matches!(expr.eval(), Ok(Some(EVar::FVal(f))) if (f-(30.0f64).sin()).abs() < 1e-12));
+}
+
+#[test]
+pub fn rpn_test_long_expression() {
+	// generated formula with hundreds of terms: 1+2-3+4-5...
+	let mut exp=String::from("1");
+	let mut expected=1;
+	for i in 2..500 {
+		exp.push_str(if i%2 == 0 {"+"} else {"-"});
+		exp.push_str(&i.to_string());
+		expected += if i%2 == 0 {i} else {-i};
+	}
+	let mut expr=rpn::Expression::new(&exp, None);
+	assert!(expr.eval().ok() == Some(Some(EVar::IVal(expected))));
+}
+
+fn eval_test(params:&[EVar]) -> Result<EVar, RpnError> {
+	Ok(params[0].clone())
 }
 
 /*
@@ -199,4 +693,228 @@ pub fn rpn_test_evar() {
 	println!("elapsed for copy {:#?}", start.elapsed());
 
 }
- */
\ No newline at end of file
+ */
+
+#[test]
+pub fn rpn_test_repl() {
+	let mut child=Command::new(env!("CARGO_BIN_EXE_rpn")).stdin(Stdio::piped()).stdout(Stdio::piped())
+		.spawn().expect("rpn binary");
+	let input="1+2\nans*10\n$1 + $2\n\n:tokens 2*(x+1)\n:rpn 2*(x+1)\n:rpn 1+sinn(2)\n:tokens \"abc\n:tokens (1\n1+sinn(2)\n:stack\n3 4 + 2 *\ndup\n:infix\nans\n:history\n:quit\n4\n";
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	let output=child.wait_with_output().unwrap();
+	let lines:Vec<String>=String::from_utf8_lossy(&output.stdout).lines().map(|l| l.to_string()).collect();
+	assert!(lines == [
+		"$1 = 3",
+		"$2 = 30",
+		"$3 = 33",
+		"2 * ( x + 1 ) ",
+		"2 x 1 +(bi) *(bi) ",
+		"error: unknown function 'sinn' at column 3",
+		"error: unterminated string '\"abc' at column 1",
+		"( 1 ",
+		"error: unknown function 'sinn' at column 3",
+		"14",
+		"14 14",
+		"$4 = 33",
+		"$1 = 3\t1+2",
+		"$2 = 30\tans*10",
+		"$3 = 33\t$1 + $2",
+		"$4 = 33\tans",
+	], "{:?}", lines);
+}
+
+#[test]
+pub fn rpn_test_stack_calc() {
+	let mut calc=rpn::stack::StackCalc::new();
+	assert!(calc.eval("3 4 + 2 *", None).ok() == Some(Some(EVar::IVal(14))));
+	// the stack is kept from one input to the next
+	assert!(calc.eval("dup *", None).ok() == Some(Some(EVar::IVal(196))));
+	assert!(calc.eval("clear 1 2 3 rot", None).is_ok() && calc.stack() == [EVar::IVal(2), EVar::IVal(3), EVar::IVal(1)]);
+	assert!(calc.eval("swap drop neg", None).is_ok() && calc.stack() == [EVar::IVal(2), EVar::IVal(-1)]);
+	// functions with their number of parameters, or the whole stack when variable
+	assert!(calc.eval("drop 10 pow", None).ok() == Some(Some(EVar::IVal(1024))));
+	assert!(calc.eval("clear 2 ! 9 sqrt 1.5 2 pi max", None).ok() == Some(Some(EVar::FVal(std::f64::consts::PI))));
+	assert!(calc.eval("clear \"abc\" upper 'd' +", None).ok() == Some(Some(EVar::SVal("ABCd".to_string()))));
+	// signed numbers, and '-' as operator when separated from the number
+	assert!(calc.eval("clear 1 5 -3 +", None).is_ok() && calc.stack() == [EVar::IVal(1), EVar::IVal(2)]);
+	assert!(calc.eval("clear 1 5 - 3", None).is_ok() && calc.stack() == [EVar::IVal(-4), EVar::IVal(3)]);
+	assert!(calc.eval("clear -2.5 +4 -", None).ok() == Some(Some(EVar::FVal(-6.5))));
+	// a failing input leaves the stack unchanged
+	calc.clear();
+	assert!(calc.eval("1 2", None).is_ok());
+	let e=calc.eval("3 + * +", None).err().unwrap();
+	assert!(e.error == RpnError::Exec(ExErr::GetOperandMissing) && e.column() == Some(7), "{}", e);
+	assert!(calc.eval("1 undo", None).is_err());
+	assert!(calc.eval("1 (", None).is_err());
+	assert!(calc.stack() == [EVar::IVal(1), EVar::IVal(2)]);
+	// functions and constants of the context
+	let mut context=EvalContext::new();
+	context.register_fn("twice", Some(1), |params| Ok(params[0].clone()*EVar::IVal(2)));
+	context.register_const("k", EVar::IVal(5));
+	assert!(calc.eval("k twice", Some(&context)).ok() == Some(Some(EVar::IVal(10))));
+}
+
+#[test]
+pub fn rpn_test_csv() {
+	let mut child=Command::new(env!("CARGO_BIN_EXE_rpn"))
+		.args(["csv", "flow=level/(time-2)", "high=flow > 2", "tag=name + \"!\""])
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("rpn binary");
+	let input="time,level,name\n1,2.5,\"a, b\"\n2,4,x\n4,,\"q\"\"y\"\n";
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	let output=child.wait_with_output().unwrap();
+	assert!(String::from_utf8_lossy(&output.stdout) == "time,level,name,flow,high,tag\n\
+		1,2.5,\"a, b\",-2.5,false,\"a, b!\"\n\
+		2,4,x,,false,x!\n\
+		4,,\"q\"\"y\",,false,\"q\"\"y!\"\n", "{}", String::from_utf8_lossy(&output.stdout));
+	// the rows which could not be evaluated are reported
+	assert!(String::from_utf8_lossy(&output.stderr).lines().collect::<Vec<&str>>() == [
+		"line 3: flow: division by zero in '/' at column 6"]);
+	assert!(output.status.code() == Some(1));
+	// rows with missing or extra fields
+	let mut child=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", "d=a+1"])
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("rpn binary");
+	child.stdin.take().unwrap().write_all(b"a,b,w\n4,5\n1,2,3,4\n").unwrap();
+	let output=child.wait_with_output().unwrap();
+	assert!(String::from_utf8_lossy(&output.stdout) == "a,b,w,d\n4,5,,5\n1,2,3,2\n", "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(String::from_utf8_lossy(&output.stderr).lines().collect::<Vec<&str>>() == [
+		"line 2: 2 fields instead of 3", "line 3: 4 fields instead of 3"]);
+	assert!(output.status.code() == Some(1));
+	// spaces around the names of the columns
+	let mut child=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", "double=level*2"])
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("rpn binary");
+	child.stdin.take().unwrap().write_all(b"time, level \n1, 3\n").unwrap();
+	let output=child.wait_with_output().unwrap();
+	assert!(String::from_utf8_lossy(&output.stdout) == "time,level,double\n1, 3,6\n", "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(output.status.success() && output.stderr.is_empty());
+	// columns named as constants or functions
+	for header in ["e,c,h", "a,count"] {
+		let mut child=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", "d=1"])
+			.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("rpn binary");
+		child.stdin.take().unwrap().write_all(format!("{}\n1,2,3\n", header).as_bytes()).unwrap();
+		let output=child.wait_with_output().unwrap();
+		assert!(output.status.code() == Some(2) && output.stdout.is_empty(), "{}", header);
+		assert!(String::from_utf8_lossy(&output.stderr).ends_with("is the name of a constant or a function\n"));
+	}
+	// names of the definitions
+	for (def, msg) in [("bad name=1", "invalid name 'bad name'"), ("2x=1", "invalid name '2x'"), ("pi=3", "'pi' is the name of a constant or a function"),
+		("x=x*2", "'x' already defined as a column")] {
+		let mut child=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", def])
+			.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("rpn binary");
+		child.stdin.take().unwrap().write_all(b"x\n1\n").unwrap();
+		let output=child.wait_with_output().unwrap();
+		assert!(output.status.code() == Some(2) && output.stdout.is_empty(), "{}", def);
+		assert!(String::from_utf8_lossy(&output.stderr) == format!("rpn csv: {}\n", msg), "{}", String::from_utf8_lossy(&output.stderr));
+	}
+	let output=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", "y=1", "y=2"]).stdin(Stdio::null()).output().unwrap();
+	assert!(output.status.code() == Some(2) && String::from_utf8_lossy(&output.stderr) == "rpn csv: 'y' already defined\n");
+	// a definition which cannot be parsed stops everything
+	let output=Command::new(env!("CARGO_BIN_EXE_rpn")).args(["csv", "x=1+sinn(2)"]).stdin(Stdio::null())
+		.output().unwrap();
+	assert!(output.status.code() == Some(2) && output.stdout.is_empty());
+}
+
+#[test]
+pub fn rpn_test_derived() {
+	use rpn::derived::*;
+	// definitions in any order, using inputs and each other
+	let text="# tank
+volume = level * area
+area = pi * radius ** 2
+
+ratio = volume / capacity
+capacity = 1000
+alarm = ratio > 0.9 || isnull(level)
+";
+	let mut defs=Definitions::parse(text, None).unwrap();
+	assert!(defs.names() == ["area", "volume", "capacity", "ratio", "alarm"], "{:?}", defs.names());
+	assert!(defs.inputs() == ["level", "radius"]);
+	let inputs=HashMap::from([("level".to_string(), EVar::FVal(35.0)), ("radius".to_string(), EVar::IVal(3))]);
+	let values=defs.eval(&inputs).unwrap();
+	assert!(values.len() == 5 && values["capacity"] == EVar::IVal(1000));
+	assert!(matches!(values["volume"], EVar::FVal(v) if (v-35.0*9.0*std::f64::consts::PI).abs() < 1e-9));
+	assert!(values["alarm"] == EVar::BVal(true));
+	let inputs=HashMap::from([("level".to_string(), EVar::IVal(1)), ("radius".to_string(), EVar::IVal(3))]);
+	assert!(defs.eval(&inputs).unwrap()["alarm"] == EVar::BVal(false));
+	// missing input
+	let e=defs.eval(&HashMap::new()).err().unwrap();
+	assert!(e.to_string() == "line 3: area: no value for variable 'radius' at column 13", "{}", e);
+	// errors of the definitions
+	let tests=[
+		("a = 1\nb = a + sinn(2)", "line 2: b: unknown function 'sinn' at column 9"),
+		("a = 1\n2 = a", "line 2: 'name = expression' expected"),
+		("a = 1\nb = 2\na = 3", "line 3: 'a' already defined"),
+		("u = v + 1\nv = w * 2\nw = u - x", "line 1: circular definition u -> v -> w -> u"),
+		("x = 1\ny = y + x", "line 2: circular definition y -> y"),
+		("pi = 3", "line 1: 'pi' is the name of a constant or a function"),
+		("sin = 1", "line 1: 'sin' is the name of a constant or a function"),
+		("a = 1\nsqrt = 2", "line 2: 'sqrt' is the name of a constant or a function"),
+	];
+	for (text, msg) in tests {
+		let e=Definitions::parse(text, None).err().unwrap();
+		assert!(e.to_string() == msg, "{}", e);
+	}
+	assert!(matches!(Definitions::parse("a = b\nb = a", None), Err(DefError{error:DefErr::Cycle(_), ..})));
+	assert!(matches!(Definitions::load(std::path::Path::new("/nonexistent/defs.txt"), None), Err(DefError{error:DefErr::File(_), ..})));
+}
+
+#[test]
+pub fn rpn_test_workspace() {
+	use rpn::workspace::Workspace;
+	// evaluations counted by a function wrapping each definition
+	let calls=Rc::new(Cell::new(0));
+	let counter=Rc::clone(&calls);
+	let mut context=EvalContext::new();
+	context.register_fn("cnt", Some(1), move |params| {
+		counter.set(counter.get()+1);
+		Ok(params[0].clone())
+	});
+	let text="\
+total = cnt(t1 + t2)
+t1 = cnt(a * 2)
+t2 = cnt(b + d)
+high = cnt(total > 100)
+ratio = cnt(d / b)
+";
+	let mut ws=Workspace::parse(text, Some(&context)).unwrap();
+	assert!(ws.names() == ["t1", "t2", "total", "high", "ratio"]);
+	assert!(ws.inputs() == ["a", "b", "d"]);
+	assert!(!ws.set("x", EVar::IVal(1)) && !ws.set("total", EVar::IVal(1)));
+	// first update: all the definitions, those without their inputs failing (before calling cnt)
+	assert!(ws.set("a", EVar::IVal(10)));
+	assert!(ws.update() == ["t1", "high"] && calls.get() == 3);
+	// total is null, and null > 100 is false
+	assert!(ws.value("t2") == Some(&EVar::Null) && ws.value("t1") == Some(&EVar::IVal(20)) && ws.value("high") == Some(&EVar::BVal(false)));
+	assert!(ws.errors().count() == 2);
+	let e=ws.error("ratio").unwrap();
+	assert!(e.to_string() == "line 5: ratio: no value for variable 'd' at column 13", "{}", e);
+	// t2 and ratio, then total and high using t2
+	calls.set(0);
+	ws.set("b", EVar::IVal(20));
+	ws.set("d", EVar::IVal(40));
+	assert!(ws.update() == ["t2", "total", "ratio"] && calls.get() == 4);
+	assert!(ws.errors().count() == 0 && ws.value("total") == Some(&EVar::IVal(80)) && ws.value("high") == Some(&EVar::BVal(false)));
+	// only t1, total and high
+	calls.set(0);
+	ws.set("a", EVar::IVal(11));
+	assert!(ws.update() == ["t1", "total"] && calls.get() == 3);
+	assert!(ws.value("total") == Some(&EVar::IVal(82)));
+	// nothing changed
+	calls.set(0);
+	ws.set("a", EVar::IVal(11));
+	assert!(ws.update().is_empty() && calls.get() == 0);
+	// t2 evaluated, but unchanged: its users are not evaluated
+	ws.set("b", EVar::IVal(30));
+	ws.set("d", EVar::IVal(30));
+	assert!(ws.update() == ["ratio"] && calls.get() == 2);
+	// a change of type is a change
+	calls.set(0);
+	ws.set("a", EVar::FVal(11.0));
+	assert!(ws.update() == ["t1", "total"] && calls.get() == 3);
+	assert!(ws.value("a") == Some(&EVar::FVal(11.0)));
+	// a failing definition is null
+	ws.set("b", EVar::IVal(0));
+	assert!(ws.update() == ["t2", "total", "ratio"]);
+	assert!(ws.value("ratio") == Some(&EVar::Null) && ws.error("ratio").is_some() && ws.error("t2").is_none());
+	ws.set("b", EVar::IVal(1));
+	assert!(ws.update() == ["t2", "total", "ratio"] && ws.errors().count() == 0);
+}